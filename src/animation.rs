@@ -0,0 +1,157 @@
+//! Animated GIF and APNG files from RGBA frames.
+
+use std::error::Error;
+use std::io::Write;
+
+/// A single RGBA frame of an animation, and how long to show it for in milliseconds.
+pub struct Frame {
+    pub data: Vec<u8>,
+    pub delay: u32,
+}
+
+/// Writes the frames as a looping animated GIF.
+///
+/// Each frame gets its own palette, quantized from the frame's colors.
+pub fn write_gif(
+    w: impl Write,
+    width: u32,
+    height: u32,
+    frames: &[Frame],
+) -> Result<(), Box<dyn Error>> {
+    let mut encoder = gif::Encoder::new(w, width as u16, height as u16, &[])?;
+    encoder.set_repeat(gif::Repeat::Infinite)?;
+    for frame in frames {
+        let mut data = frame.data.clone();
+        let mut gif_frame = gif::Frame::from_rgba_speed(width as u16, height as u16, &mut data, 10);
+        // GIF delays are in hundredths of a second.
+        gif_frame.delay = (frame.delay / 10).min(u16::MAX as u32) as u16;
+        encoder.write_frame(&gif_frame)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the frames as a looping APNG.
+pub fn write_apng(
+    w: impl Write,
+    width: u32,
+    height: u32,
+    frames: &[Frame],
+) -> Result<(), Box<dyn Error>> {
+    let mut encoder = png_encoder::Encoder::new(w, width, height);
+    encoder.set_color(png_encoder::ColorType::Rgba);
+    encoder.set_depth(png_encoder::BitDepth::Eight);
+    encoder.set_animated(frames.len() as u32, 0)?;
+    let mut writer = encoder.write_header()?;
+    for frame in frames {
+        writer.set_frame_delay(frame.delay.min(u16::MAX as u32) as u16, 1000)?;
+        writer.write_image_data(&frame.data)?;
+    }
+    writer.finish()?;
+
+    Ok(())
+}
+
+/// Extends an RGBA image of `size` to `new_size` by repeating its last row and column.
+pub fn pad_image(data: &[u8], size: (u32, u32), new_size: (u32, u32)) -> Vec<u8> {
+    if size == new_size || size.0 == 0 || size.1 == 0 {
+        return data.to_vec();
+    }
+    let (width, height) = (size.0 as usize, size.1 as usize);
+    let mut padded = Vec::with_capacity(new_size.0 as usize * new_size.1 as usize * 4);
+    for y in 0..new_size.1 as usize {
+        let row = &data[y.min(height - 1) * width * 4..][..width * 4];
+        padded.extend_from_slice(row);
+        for _ in width..new_size.0 as usize {
+            padded.extend_from_slice(&row[(width - 1) * 4..]);
+        }
+    }
+
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An RGBA image with `colors` distinct colors, all exactly representable in a GIF palette.
+    fn test_image(width: u32, height: u32, colors: u32) -> Vec<u8> {
+        (0..width * height)
+            .flat_map(|i| {
+                let color = (i * 7919 + i / 3) % colors;
+                vec![(color % 32 * 8) as u8, (color / 32 * 8) as u8, 128, 255]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn gif_round_trip() {
+        let (width, height) = (97, 61);
+        let frames = vec![
+            Frame {
+                data: test_image(width, height, 256),
+                delay: 500,
+            },
+            Frame {
+                data: test_image(width, height, 3),
+                delay: 1000,
+            },
+        ];
+        let mut encoded = vec![];
+        write_gif(&mut encoded, width, height, &frames).unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::RGBA);
+        let mut decoder = options.read_info(&encoded[..]).unwrap();
+        for frame in &frames {
+            let decoded = decoder.read_next_frame().unwrap().unwrap();
+            assert_eq!(
+                (decoded.width, decoded.height),
+                (width as u16, height as u16)
+            );
+            assert_eq!(decoded.delay as u32, frame.delay / 10);
+            assert_eq!(&decoded.buffer[..], &frame.data[..]);
+        }
+        assert!(decoder.read_next_frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn apng_round_trip() {
+        let (width, height) = (13, 7);
+        let frames = vec![
+            Frame {
+                data: test_image(width, height, 50),
+                delay: 300,
+            },
+            Frame {
+                data: test_image(width, height, 5),
+                delay: 700,
+            },
+        ];
+        let mut encoded = vec![];
+        write_apng(&mut encoded, width, height, &frames).unwrap();
+
+        let decoder = png_encoder::Decoder::new(&encoded[..]);
+        let mut reader = decoder.read_info().unwrap();
+        assert_eq!((reader.info().width, reader.info().height), (width, height));
+        let animation_control = reader.info().animation_control().unwrap();
+        assert_eq!(animation_control.num_frames, 2);
+        assert_eq!(animation_control.num_plays, 0);
+        for frame in &frames {
+            let mut buffer = vec![0; reader.output_buffer_size()];
+            reader.next_frame(&mut buffer).unwrap();
+            let frame_control = reader.info().frame_control().unwrap();
+            assert_eq!(frame_control.delay_num as u32, frame.delay);
+            assert_eq!(frame_control.delay_den, 1000);
+            assert_eq!(buffer, frame.data);
+        }
+    }
+
+    #[test]
+    fn pad_image_repeats_the_edges() {
+        let data = vec![1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4];
+        let padded = pad_image(&data, (2, 2), (3, 3));
+        let pixels: Vec<u8> = padded.chunks(4).map(|pixel| pixel[0]).collect();
+        assert_eq!(pixels, vec![1, 2, 2, 3, 4, 4, 3, 4, 4]);
+    }
+}
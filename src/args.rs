@@ -1,8 +1,14 @@
-use crate::lib::{GobanRange, MakeSvgOptions};
+use crate::lib::{CoordinateScheme, Coordinates, CroppedEdges, GobanRange, MakeSvgOptions, Theme};
+use crate::output::OutputFormat;
+use std::ops::Range;
 use std::path::PathBuf;
 
+const DEFAULT_GAME: usize = 1;
 const DEFAULT_MOVE_NUMBER: u64 = 1;
 const DEFAULT_WIDTH: u32 = 800;
+const DEFAULT_FRAME_DELAY: u32 = 500;
+const DEFAULT_NAME_TEMPLATE: &str = "{file}-{game}.svg";
+const DEFAULT_GRID: (usize, usize) = (2, 3);
 
 pub fn parse_args(
     opts: &getopts::Options,
@@ -11,23 +17,139 @@ pub fn parse_args(
     let matches = opts
         .parse(&args[1..])
         .map_err(|_| UsageError::FailedToParse)?;
-    if matches.free.len() > 1 {
+    let outdir = matches.opt_str("outdir").map(PathBuf::from);
+    let book = matches.opt_present("book");
+    if outdir.is_none() && !book && matches.free.len() > 1 {
         return Err(UsageError::TooManyArguments);
     }
-    let infile = matches.free.first().map(PathBuf::from);
+    let infiles: Vec<PathBuf> = matches.free.iter().map(PathBuf::from).collect();
+    let infile = infiles.first().cloned();
     let outfile = matches.opt_str("o").map(PathBuf::from);
+    let format = match matches.opt_str("format").as_deref() {
+        Some("svg") => Some(OutputFormat::Svg),
+        Some("ascii") => Some(OutputFormat::Ascii),
+        Some("unicode") => Some(OutputFormat::Unicode {
+            color: matches.opt_present("color"),
+        }),
+        Some("sensei") => Some(OutputFormat::Sensei),
+        Some("igo") => Some(OutputFormat::Igo),
+        Some("psgo") => Some(OutputFormat::Psgo),
+        Some(_) => return Err(UsageError::InvalidFormat),
+        None => None,
+    };
+    let name_template = matches
+        .opt_str("name-template")
+        .unwrap_or_else(|| DEFAULT_NAME_TEMPLATE.to_string());
+    let game = match matches.opt_str("game").map(|c| c.parse()) {
+        Some(Ok(0)) | Some(Err(_)) => return Err(UsageError::InvalidGame),
+        Some(Ok(game)) => game,
+        None => DEFAULT_GAME,
+    };
+    let list_games = matches.opt_present("list-games");
     let move_number = matches
         .opt_str("m")
         .map(|c| c.parse())
         .unwrap_or(Ok(DEFAULT_MOVE_NUMBER))
         .map_err(|_| UsageError::InvalidMoveNumber)?;
+    let node_path = {
+        if matches.opt_present("variation") && matches.opt_present("node-path") {
+            return Err(UsageError::OverspecifiedVariation);
+        }
+
+        if let Some(s) = matches.opt_str("variation") {
+            parse_variation(&s)?
+        } else if let Some(s) = matches.opt_str("node-path") {
+            parse_node_path(&s)?
+        } else {
+            NodePath::MainLine
+        }
+    };
     let render_labels = !matches.opt_present("no-labels");
+    let render_score = matches.opt_present("score");
+    let coordinates = {
+        let scheme = match matches.opt_str("coordinates").as_deref() {
+            Some("letters") | None => CoordinateScheme::Letters,
+            Some("sgf") => CoordinateScheme::Sgf,
+            Some("numbers") => CoordinateScheme::Numbers,
+            Some("kanji") => CoordinateScheme::Kanji,
+            Some(_) => return Err(UsageError::InvalidCoordinates),
+        };
+        let rows_from_top = match matches.opt_str("rows").as_deref() {
+            Some("up") => false,
+            Some("down") => true,
+            None => scheme.rows_from_top(),
+            Some(_) => return Err(UsageError::InvalidRowOrder),
+        };
+        let sides = match matches.opt_str("label-sides") {
+            Some(s) => parse_label_sides(&s)?,
+            None => Coordinates::default().sides,
+        };
+        Coordinates {
+            scheme,
+            rows_from_top,
+            sides,
+        }
+    };
+    let move_numbers = match matches.opt_str("move-numbers") {
+        Some(s) => Some(parse_move_range(&s)?),
+        None => None,
+    };
     let viewbox_width = matches
         .opt_str("w")
         .map(|c| c.parse::<u32>())
         .unwrap_or(Ok(DEFAULT_WIDTH))
         .map_err(|_| UsageError::InvalidWidth)? as f64;
+    let cropped_edges = match matches.opt_str("cropped-edges").as_deref() {
+        Some("lines") | None => CroppedEdges::Lines,
+        Some("fade") => CroppedEdges::Fade,
+        Some("jagged") => CroppedEdges::Jagged,
+        Some(_) => return Err(UsageError::InvalidCroppedEdges),
+    };
+    let mut theme = match matches.opt_str("style").as_deref() {
+        Some("default") | None => Theme::default(),
+        Some("print") => Theme::print(),
+        Some(_) => return Err(UsageError::InvalidStyle),
+    };
+    if let Some(filename) = matches.opt_str("theme") {
+        let text = std::fs::read_to_string(&filename)
+            .map_err(|e| UsageError::InvalidTheme(e.to_string()))?;
+        theme
+            .load(&text)
+            .map_err(|e| UsageError::InvalidTheme(e.to_string()))?;
+    }
     let print_help = matches.opt_present("h");
+    let all_moves = matches.opt_present("all-moves");
+    let animate = matches.opt_present("animate")
+        || match outfile.as_ref().and_then(|f| f.extension()) {
+            Some(extension) => extension == "gif" || extension == "apng",
+            None => false,
+        };
+    let frame_delay = matches
+        .opt_str("frame-delay")
+        .map(|c| c.parse())
+        .unwrap_or(Ok(DEFAULT_FRAME_DELAY))
+        .map_err(|_| UsageError::InvalidDelay)?;
+    let comment_delay = matches
+        .opt_str("comment-delay")
+        .map(|c| c.parse())
+        .transpose()
+        .map_err(|_| UsageError::InvalidDelay)?;
+    let figures = matches.opt_present("figures") || matches.opt_present("figure-size");
+    let figure_moves = matches
+        .opt_str("figure-size")
+        .map(|c| c.parse::<u64>())
+        .transpose()
+        .map_err(|_| UsageError::InvalidFigureMoves)?;
+    let book_grid = match matches.opt_str("grid") {
+        Some(s) => parse_grid(&s)?,
+        None => DEFAULT_GRID,
+    };
+    let page_size = match matches.opt_str("page-size").as_deref() {
+        Some("a4") | None => PageSize::A4,
+        Some("letter") => PageSize::Letter,
+        Some(_) => return Err(UsageError::InvalidPageSize),
+    };
+    let book_answers = matches.opt_present("answers");
     let goban_range = {
         if matches.opt_present("shrink-wrap") && matches.opt_present("r") {
             return Err(UsageError::OverspecifiedRange);
@@ -46,20 +168,47 @@ pub fn parse_args(
     let options = MakeSvgOptions {
         goban_range,
         render_labels,
+        coordinates,
         viewbox_width,
+        render_score,
+        move_numbers,
+        animation: None,
+        cropped_edges,
+        theme,
     };
 
     Ok(SgfRenderArgs {
         infile,
+        infiles,
         outfile,
+        format,
+        outdir,
+        name_template,
+        game,
+        list_games,
         move_number,
+        node_path,
         options,
+        animate,
+        frame_delay,
+        comment_delay,
+        all_moves,
+        figures,
+        figure_moves,
+        book,
+        book_grid,
+        page_size,
+        book_answers,
         print_help,
     })
 }
 
 pub fn print_usage(program: &str, opts: &getopts::Options) {
-    let brief = format!("Usage: {} [FILE] [options]", program);
+    let brief = format!(
+        "Usage: {} [FILE] [options]\n       {} --outdir DIR [FILE|DIR|PATTERN]... [options]\n       \
+         {} --book -o FILE.pdf [FILE|DIR|PATTERN]... [options]",
+        program, program, program
+    );
     print!("{}", opts.usage(&brief));
 }
 
@@ -68,15 +217,72 @@ pub fn build_opts() -> getopts::Options {
     opts.optopt(
         "o",
         "outfile",
-        "Output file. SVG and PNG formats supported.",
+        "Output file. SVG, PNG, PDF, text (.txt) and LaTeX (.tex) formats supported, and GIF \
+         and APNG for animations of the moves from '-m' on.",
         "FILE",
     );
+    opts.optopt(
+        "",
+        "format",
+        "Output format, 'svg', 'ascii', 'unicode', 'sensei' for Sensei's Library diagrams, or \
+         'igo' or 'psgo' for LaTeX (default from the output file extension, or svg)",
+        "FORMAT",
+    );
+    opts.optflag(
+        "",
+        "color",
+        "Draw unicode output on a wood background with ANSI colors",
+    );
+    opts.optopt(
+        "",
+        "outdir",
+        "Batch mode. Render every game in the input files, directories, or patterns (e.g. \
+         'games/*.sgf') into DIR",
+        "DIR",
+    );
+    opts.optopt(
+        "",
+        "name-template",
+        &format!(
+            "File name template for batch mode, using {{file}}, {{game}}, {{move}} and game \
+             info properties like {{GN}} (default '{}')",
+            DEFAULT_NAME_TEMPLATE,
+        ),
+        "TEMPLATE",
+    );
+    opts.optopt(
+        "g",
+        "game",
+        &format!(
+            "Game to render from a multi-game collection (default {})",
+            DEFAULT_GAME,
+        ),
+        "NUM",
+    );
+    opts.optflag(
+        "",
+        "list-games",
+        "List the games in the collection and exit",
+    );
     opts.optopt(
         "m",
         "move-num",
         &format!("Move number to render (default {})", DEFAULT_MOVE_NUMBER,),
         "NUM",
     );
+    opts.optopt(
+        "",
+        "variation",
+        "Variation to follow, as the branch to take at each branch point in turn, with 0 \
+         for the main line (e.g. '2.1')",
+        "PATH",
+    );
+    opts.optopt(
+        "",
+        "node-path",
+        "Variation to follow, as the branch to take at given nodes (e.g. '12:1/30:2')",
+        "PATH",
+    );
     opts.optopt(
         "w",
         "width",
@@ -98,6 +304,120 @@ pub fn build_opts() -> getopts::Options {
         "RANGE",
     );
     opts.optflag("", "no-labels", "Don't render labels on the diagram");
+    opts.optopt(
+        "",
+        "label-sides",
+        "Sides of the board to label, any of 't', 'r', 'b' and 'l' (default 'tl')",
+        "SIDES",
+    );
+    opts.optopt(
+        "",
+        "coordinates",
+        "Coordinate labels, 'letters' for A-T and numbers, 'sgf' for SGF letters, 'numbers' \
+         for numbers on both axes, or 'kanji' for numbers and Japanese/Chinese numerals \
+         (default 'letters')",
+        "SCHEME",
+    );
+    opts.optopt(
+        "",
+        "rows",
+        "Count rows 'up' from the bottom or 'down' from the top (default 'down' for sgf and \
+         kanji coordinates, 'up' otherwise)",
+        "ORDER",
+    );
+    opts.optopt(
+        "",
+        "cropped-edges",
+        "How to draw sides cropped by '-r' or '-s', 'lines' to run the lines on past the crop, \
+         'fade' or 'jagged' (default 'lines')",
+        "STYLE",
+    );
+    opts.optopt(
+        "",
+        "move-numbers",
+        "Number the stones played in a range of moves (e.g. '1-50')",
+        "RANGE",
+    );
+    opts.optflag(
+        "",
+        "score",
+        "Print the territory and area scores from TB/TW under the board",
+    );
+    opts.optopt(
+        "",
+        "style",
+        "Built-in style, 'default' or 'print' for a monochrome style without shadows",
+        "STYLE",
+    );
+    opts.optopt(
+        "",
+        "theme",
+        "TOML or JSON file overriding the colors, fonts and sizes of the style",
+        "FILE",
+    );
+    opts.optflag(
+        "",
+        "animate",
+        "Write an SVG animating the moves from '-m' on (implied for GIF and APNG)",
+    );
+    opts.optopt(
+        "",
+        "frame-delay",
+        &format!(
+            "Time to show each frame of an animation in milliseconds (default {})",
+            DEFAULT_FRAME_DELAY,
+        ),
+        "MS",
+    );
+    opts.optopt(
+        "",
+        "comment-delay",
+        "Time to show animation frames for nodes with comments in milliseconds",
+        "MS",
+    );
+    opts.optflag(
+        "",
+        "all-moves",
+        "Write every node as a numbered frame (e.g. 'frame-001.png' for '-o frame.png')",
+    );
+    opts.optflag(
+        "",
+        "figures",
+        "Split the game into numbered figures (e.g. 'fig-01.svg' for '-o fig.svg') at every FG \
+         property, following '--variation' or '--node-path' if given",
+    );
+    opts.optopt(
+        "",
+        "figure-size",
+        "Also start a new figure every MOVES moves (implies '--figures')",
+        "MOVES",
+    );
+    opts.optflag(
+        "",
+        "book",
+        "Book mode. Lay out every game in the input files, directories, or patterns as numbered \
+         problems in a multi-page PDF",
+    );
+    opts.optopt(
+        "",
+        "grid",
+        &format!(
+            "Problems per page in book mode as columns and rows (default '{}x{}')",
+            DEFAULT_GRID.0, DEFAULT_GRID.1,
+        ),
+        "GRID",
+    );
+    opts.optopt(
+        "",
+        "page-size",
+        "Paper size for book mode, 'a4' or 'letter' (default 'a4')",
+        "SIZE",
+    );
+    opts.optflag(
+        "",
+        "answers",
+        "Add an answer section to the book showing the first variation of each problem",
+    );
     opts.optflag("h", "help", "Display this help and exit");
 
     opts
@@ -106,20 +426,112 @@ pub fn build_opts() -> getopts::Options {
 #[derive(Debug)]
 pub struct SgfRenderArgs {
     pub infile: Option<PathBuf>,
+    pub infiles: Vec<PathBuf>,
     pub outfile: Option<PathBuf>,
+    pub format: Option<OutputFormat>,
+    pub outdir: Option<PathBuf>,
+    pub name_template: String,
+    pub game: usize,
+    pub list_games: bool,
     pub move_number: u64,
+    pub node_path: NodePath,
     pub options: MakeSvgOptions,
+    pub animate: bool,
+    pub frame_delay: u32,
+    pub comment_delay: Option<u32>,
+    pub all_moves: bool,
+    pub figures: bool,
+    pub figure_moves: Option<u64>,
+    pub book: bool,
+    // Only book mode reads these, and it needs pdf support.
+    #[cfg_attr(not(feature = "pdf"), allow(dead_code))]
+    pub book_grid: (usize, usize),
+    #[cfg_attr(not(feature = "pdf"), allow(dead_code))]
+    pub page_size: PageSize,
+    #[cfg_attr(not(feature = "pdf"), allow(dead_code))]
+    pub book_answers: bool,
     pub print_help: bool,
 }
 
+/// Paper sizes for books.
+#[derive(Copy, Clone, Debug)]
+pub enum PageSize {
+    A4,
+    Letter,
+}
+
+/// Which branches to follow from the root to reach a node.
+#[derive(Debug)]
+pub enum NodePath {
+    MainLine,
+    /// The branch to take at each node with more than one child, in order.
+    Variations(Vec<usize>),
+    /// The branch to take at specific node numbers.
+    Branches(Vec<(u64, usize)>),
+}
+
+impl NodePath {
+    /// Returns the branch to take at `node_number`.
+    ///
+    /// `branch_point` is the number of nodes with more than one child passed so far, or `None` if
+    /// this node only has one child.
+    pub fn branch(&self, node_number: u64, branch_point: Option<usize>) -> usize {
+        match self {
+            Self::MainLine => 0,
+            Self::Variations(branches) => branch_point
+                .and_then(|i| branches.get(i))
+                .copied()
+                .unwrap_or(0),
+            Self::Branches(branches) => branches
+                .iter()
+                .find(|(n, _)| *n == node_number)
+                .map_or(0, |(_, branch)| *branch),
+        }
+    }
+}
+
+impl std::fmt::Display for NodePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::MainLine => write!(f, "main line"),
+            Self::Variations(branches) => {
+                let parts: Vec<String> = branches.iter().map(|b| b.to_string()).collect();
+                write!(f, "{}", parts.join("."))
+            }
+            Self::Branches(branches) => {
+                let parts: Vec<String> = branches
+                    .iter()
+                    .map(|(n, branch)| format!("{}:{}", n, branch))
+                    .collect();
+                write!(f, "{}", parts.join("/"))
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum UsageError {
     FailedToParse,
     TooManyArguments,
+    InvalidFormat,
+    InvalidGame,
     InvalidMoveNumber,
+    InvalidMoveRange,
+    InvalidFigureMoves,
+    InvalidDelay,
+    InvalidGrid,
+    InvalidPageSize,
     InvalidWidth,
+    InvalidCroppedEdges,
+    InvalidCoordinates,
+    InvalidRowOrder,
+    InvalidLabelSides,
+    InvalidStyle,
+    InvalidTheme(String),
     OverspecifiedRange,
     InvalidRange,
+    OverspecifiedVariation,
+    InvalidVariation,
 }
 
 impl std::fmt::Display for UsageError {
@@ -127,10 +539,27 @@ impl std::fmt::Display for UsageError {
         match self {
             UsageError::FailedToParse => write!(f, "Failed to parse arguments."),
             UsageError::TooManyArguments => write!(f, "Too many arguments."),
+            UsageError::InvalidFormat => write!(f, "Invalid output format."),
+            UsageError::InvalidGame => write!(f, "Invalid game number."),
             UsageError::InvalidMoveNumber => write!(f, "Invalid move number."),
+            UsageError::InvalidMoveRange => write!(f, "Invalid move number range."),
+            UsageError::InvalidFigureMoves => write!(f, "Invalid number of moves per figure."),
+            UsageError::InvalidDelay => write!(f, "Invalid frame delay."),
+            UsageError::InvalidGrid => write!(f, "Invalid grid."),
+            UsageError::InvalidPageSize => write!(f, "Invalid page size."),
             UsageError::InvalidWidth => write!(f, "Invalid width."),
+            UsageError::InvalidCoordinates => write!(f, "Invalid coordinate scheme."),
+            UsageError::InvalidRowOrder => write!(f, "Invalid row order."),
+            UsageError::InvalidLabelSides => write!(f, "Invalid label sides."),
+            UsageError::InvalidCroppedEdges => write!(f, "Invalid cropped edge style."),
+            UsageError::InvalidStyle => write!(f, "Invalid style."),
+            UsageError::InvalidTheme(e) => write!(f, "Invalid theme: {}", e),
             UsageError::OverspecifiedRange => write!(f, "Specify only '-r' or '-s'"),
             UsageError::InvalidRange => write!(f, "Invalid range."),
+            UsageError::OverspecifiedVariation => {
+                write!(f, "Specify only '--variation' or '--node-path'")
+            }
+            UsageError::InvalidVariation => write!(f, "Invalid variation path."),
         }
     }
 }
@@ -155,3 +584,91 @@ fn parse_point_pair(s: &str) -> Result<GobanRange, UsageError> {
         parse_byte(s[1])?..parse_byte(s[4])? + 1,
     ))
 }
+
+fn parse_move_range(s: &str) -> Result<Range<u64>, UsageError> {
+    let (start, end) = s.split_once('-').ok_or(UsageError::InvalidMoveRange)?;
+    let start: u64 = start.parse().map_err(|_| UsageError::InvalidMoveRange)?;
+    let end: u64 = end.parse().map_err(|_| UsageError::InvalidMoveRange)?;
+    if start > end {
+        return Err(UsageError::InvalidMoveRange);
+    }
+    Ok(start..end + 1)
+}
+
+fn parse_grid(s: &str) -> Result<(usize, usize), UsageError> {
+    let (columns, rows) = s.split_once('x').ok_or(UsageError::InvalidGrid)?;
+    let columns: usize = columns.parse().map_err(|_| UsageError::InvalidGrid)?;
+    let rows: usize = rows.parse().map_err(|_| UsageError::InvalidGrid)?;
+    if columns == 0 || rows == 0 {
+        return Err(UsageError::InvalidGrid);
+    }
+    Ok((columns, rows))
+}
+
+/// Parses sides as letters from "trbl", into flags clockwise from the top.
+fn parse_label_sides(s: &str) -> Result<[bool; 4], UsageError> {
+    if s.is_empty() {
+        return Err(UsageError::InvalidLabelSides);
+    }
+    let mut sides = [false; 4];
+    for c in s.chars() {
+        let side = "trbl".find(c).ok_or(UsageError::InvalidLabelSides)?;
+        sides[side] = true;
+    }
+    Ok(sides)
+}
+
+fn parse_variation(s: &str) -> Result<NodePath, UsageError> {
+    let branches = s
+        .split('.')
+        .map(|part| part.parse().map_err(|_| UsageError::InvalidVariation))
+        .collect::<Result<_, _>>()?;
+    Ok(NodePath::Variations(branches))
+}
+
+fn parse_node_path(s: &str) -> Result<NodePath, UsageError> {
+    let branches = s
+        .split('/')
+        .map(|part| {
+            let (node, branch) = part.split_once(':').ok_or(UsageError::InvalidVariation)?;
+            let node = node.parse().map_err(|_| UsageError::InvalidVariation)?;
+            let branch = branch.parse().map_err(|_| UsageError::InvalidVariation)?;
+            Ok((node, branch))
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(NodePath::Branches(branches))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variations_round_trip() {
+        let path = parse_variation("2.0.1").unwrap();
+        assert_eq!(path.to_string(), "2.0.1");
+        assert_eq!(path.branch(5, Some(0)), 2);
+        assert_eq!(path.branch(7, Some(2)), 1);
+        assert_eq!(path.branch(9, Some(3)), 0);
+        assert_eq!(path.branch(9, None), 0);
+    }
+
+    #[test]
+    fn node_paths_round_trip() {
+        let path = parse_node_path("12:1/30:2").unwrap();
+        assert_eq!(path.to_string(), "12:1/30:2");
+        assert_eq!(path.branch(12, Some(0)), 1);
+        assert_eq!(path.branch(30, None), 2);
+        assert_eq!(path.branch(31, Some(1)), 0);
+    }
+
+    #[test]
+    fn invalid_paths_are_rejected() {
+        for variation in &["", "1..2", "a", "-1", "1.2."] {
+            assert!(parse_variation(variation).is_err(), "{}", variation);
+        }
+        for node_path in &["", "12", "12:", ":1", "12:1/", "12:1:2"] {
+            assert!(parse_node_path(node_path).is_err(), "{}", node_path);
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use crate::args::SgfRenderArgs;
+use crate::output::OutputWriter;
+use crate::{game_info, get_sgf_collection, goban_at_node, SgfRenderError};
+use sgf_parse::SgfNode;
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+/// Renders every game in every input into `outdir`, naming the files from the name template.
+///
+/// Inputs can be SGF files, directories (all the `.sgf` files inside), or file name patterns using
+/// `*` and `?`. Failures for individual games are reported and skipped, including games whose
+/// file name matches one already written, rather than overwriting it.
+pub fn render_batch(parsed_args: &SgfRenderArgs, outdir: &Path) -> Result<(), Box<dyn Error>> {
+    let infiles = expand_inputs(&parsed_args.infiles)?;
+    if infiles.is_empty() {
+        Err(SgfRenderError::NoInputFiles)?;
+    }
+    std::fs::create_dir_all(outdir)?;
+    let writer = OutputWriter::new();
+    let mut failures = 0;
+    let mut written = HashSet::new();
+    for infile in infiles {
+        let collection = match get_sgf_collection(&Some(infile.clone())) {
+            Ok(collection) => collection,
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", infile.display(), e);
+                failures += 1;
+                continue;
+            }
+        };
+        for (i, sgf_root) in collection.iter().enumerate() {
+            let filename = expand_template(
+                &parsed_args.name_template,
+                &infile,
+                i + 1,
+                parsed_args.move_number,
+                sgf_root,
+            );
+            let result = match check_file_name(&filename) {
+                Err(e) => Err(e.into()),
+                Ok(()) if !written.insert(filename.clone()) => {
+                    Err(SgfRenderError::DuplicateOutputFile(filename.clone()).into())
+                }
+                Ok(()) => goban_at_node(sgf_root, parsed_args.move_number, &parsed_args.node_path),
+            };
+            let result = result.and_then(|goban| {
+                writer.write_diagram(
+                    Some(&outdir.join(&filename)),
+                    &goban,
+                    &parsed_args.options,
+                    parsed_args.format,
+                )
+            });
+            if let Err(e) = result {
+                eprintln!(
+                    "Failed to render {} game {}: {}",
+                    infile.display(),
+                    i + 1,
+                    e
+                );
+                failures += 1;
+            }
+        }
+    }
+    if failures > 0 {
+        Err(SgfRenderError::BatchFailures(failures))?;
+    }
+
+    Ok(())
+}
+
+/// Expands directories and file name patterns into a sorted list of SGF files.
+pub fn expand_inputs(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut infiles = vec![];
+    for input in inputs {
+        let pattern = input.file_name().and_then(std::ffi::OsStr::to_str);
+        if input.is_dir() {
+            let mut files = list_dir(input, |name| name.ends_with(".sgf"))?;
+            infiles.append(&mut files);
+        } else if let Some(pattern) = pattern.filter(|p| p.contains(&['*', '?'][..])) {
+            let dir = match input.parent() {
+                Some(parent) if parent != Path::new("") => parent,
+                _ => Path::new("."),
+            };
+            let mut files = list_dir(dir, |name| wildcard_match(pattern, name))?;
+            infiles.append(&mut files);
+        } else {
+            infiles.push(input.clone());
+        }
+    }
+
+    Ok(infiles)
+}
+
+fn list_dir(dir: &Path, filter: impl Fn(&str) -> bool) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut files = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(std::ffi::OsStr::to_str);
+        if path.is_file() && name.map(&filter).unwrap_or(false) {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+/// Matches a file name against a pattern where `*` matches any run of characters and `?` matches
+/// any single character.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // matches[j] is whether the pattern so far matches the first j characters of the name.
+    let mut matches = vec![false; name.len() + 1];
+    matches[0] = true;
+    for &p in pattern.iter() {
+        let previous = matches.clone();
+        matches[0] = p == '*' && previous[0];
+        for j in 1..=name.len() {
+            matches[j] = match p {
+                '*' => previous[j] || matches[j - 1],
+                '?' => previous[j - 1],
+                c => previous[j - 1] && name[j - 1] == c,
+            };
+        }
+    }
+
+    matches[name.len()]
+}
+
+/// Fills in a file name template.
+///
+/// `{file}` is the input file name without its extension, `{game}` the game's index in the
+/// collection, `{move}` the move number, and a game info property like `{GN}` or `{PB}` that
+/// property's value.
+fn expand_template(
+    template: &str,
+    infile: &Path,
+    game: usize,
+    move_number: u64,
+    sgf_root: &SgfNode,
+) -> String {
+    let mut filename = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end,
+            None => break,
+        };
+        filename.push_str(&rest[..start]);
+        let field = &rest[start + 1..end];
+        let value = match field {
+            "file" => infile
+                .file_stem()
+                .and_then(std::ffi::OsStr::to_str)
+                .unwrap_or("")
+                .to_string(),
+            "game" => game.to_string(),
+            "move" => move_number.to_string(),
+            _ => game_info(sgf_root, field).unwrap_or_default(),
+        };
+        filename.push_str(&sanitize(&value));
+        rest = &rest[end + 1..];
+    }
+    filename.push_str(rest);
+
+    filename
+}
+
+/// Replaces characters that aren't safe in file names.
+///
+/// Trailing dots and spaces are dropped, since Windows drops them, and a value left empty (or
+/// only dots, like `..`) becomes `_`.
+fn sanitize(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    match value.trim_end_matches(&['.', ' '][..]) {
+        "" => "_".to_string(),
+        value => value.to_string(),
+    }
+}
+
+/// Checks that an expanded template names a file in the output directory on any platform.
+///
+/// Rejects names that are empty or only dots, that end in a dot or space, or that use a name
+/// Windows reserves for devices, like `CON` or `nul.svg`.
+fn check_file_name(filename: &str) -> Result<(), SgfRenderError> {
+    static RESERVED: [&str; 22] = [
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+    let stem = filename.split('.').next().unwrap_or("").trim_end();
+    let invalid = filename.chars().all(|c| c == '.')
+        || filename.ends_with(&['.', ' '][..])
+        || filename.contains(&['/', '\\'][..])
+        || RESERVED.iter().any(|name| name.eq_ignore_ascii_case(stem));
+    if invalid {
+        Err(SgfRenderError::InvalidOutputFile(filename.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards_match_runs_and_single_characters() {
+        assert!(wildcard_match("*.sgf", "game.sgf"));
+        assert!(wildcard_match("*.sgf", ".sgf"));
+        assert!(!wildcard_match("*.sgf", "game.sgf.bak"));
+        assert!(wildcard_match("game-??.sgf", "game-01.sgf"));
+        assert!(!wildcard_match("game-??.sgf", "game-1.sgf"));
+        assert!(wildcard_match("*-*-*", "a--b-"));
+        assert!(wildcard_match("**", ""));
+        assert!(!wildcard_match("?", ""));
+        assert!(wildcard_match("é*", "élan"));
+    }
+
+    #[test]
+    fn sanitize_replaces_unsafe_characters() {
+        assert_eq!(sanitize("a/b\\c:d*?\"<>|\n"), "a_b_c_d_______");
+        assert_eq!(sanitize("Honinbo 2021"), "Honinbo 2021");
+    }
+
+    #[test]
+    fn sanitize_replaces_empty_and_dot_values() {
+        assert_eq!(sanitize(""), "_");
+        assert_eq!(sanitize("."), "_");
+        assert_eq!(sanitize(".."), "_");
+        assert_eq!(sanitize("Game 1. "), "Game 1");
+        assert_eq!(sanitize(".hidden"), ".hidden");
+    }
+
+    #[test]
+    fn templates_fill_empty_and_dot_values() {
+        let collection = sgf_parse::parse("(;GN[..];B[aa])(;PB[Shusaku])").unwrap();
+        let expand = |template: &str, game: usize| {
+            expand_template(
+                template,
+                Path::new("games/k.sgf"),
+                game,
+                0,
+                &collection[game],
+            )
+        };
+        assert_eq!(expand("{GN}.png", 0), "_.png");
+        assert_eq!(expand("{GN}.png", 1), "_.png");
+        assert_eq!(expand("{file}-{PB}-{GN}.svg", 1), "k-Shusaku-_.svg");
+        assert!(check_file_name(&expand("{GN}", 0)).is_ok());
+    }
+
+    #[test]
+    fn file_names_must_be_usable_everywhere() {
+        for name in &[
+            "",
+            ".",
+            "..",
+            "game.",
+            "game ",
+            "CON",
+            "nul.svg",
+            "Com1 .png",
+            "a/b.svg",
+        ] {
+            assert!(check_file_name(name).is_err(), "{:?}", name);
+        }
+        for name in &["game.svg", "_.png", "CONTEST.svg", ".svg"] {
+            assert!(check_file_name(name).is_ok(), "{:?}", name);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+use crate::args::{PageSize, SgfRenderArgs};
+use crate::batch::expand_inputs;
+use crate::lib;
+use crate::lib::Theme;
+use crate::output::OutputWriter;
+use crate::pdf::{Page, PageItem};
+use crate::{find_node, get_sgf_collection, SgfRenderError};
+use sgf_parse::{SgfNode, SgfProp};
+use std::error::Error;
+use svg::node::element;
+use svg::Document;
+use unicode_width::UnicodeWidthStr;
+
+static PAGE_MARGIN: f64 = 48.0;
+static CELL_GAP: f64 = 24.0;
+static DIAGRAM_GAP: f64 = 4.0;
+
+/// The width of one column of display width in ems; wide characters take two columns.
+static COLUMN_WIDTH: f64 = 0.6;
+static COMMENT_LINES: usize = 3;
+
+/// Returns the width and height of a page in pixels, at 96 to the inch.
+fn page_dimensions(page_size: PageSize) -> (f64, f64) {
+    match page_size {
+        PageSize::A4 => (793.7, 1122.5),
+        PageSize::Letter => (816.0, 1056.0),
+    }
+}
+
+/// A diagram with its caption, filling one cell of the page grid.
+struct Cell {
+    title: String,
+    comment: Option<String>,
+    diagram: Document,
+}
+
+/// Lays out every game in every input as a numbered problem in a multi-page PDF.
+///
+/// Each problem shows the node picked by the move number and variation options, captioned with
+/// its number, the player to move, and the node's comment. With answers turned on, the problems
+/// are followed by a section showing the first variation from each problem node to its end, with
+/// the moves numbered.
+pub fn render_book(parsed_args: &SgfRenderArgs) -> Result<(), Box<dyn Error>> {
+    let outfile = parsed_args
+        .outfile
+        .as_ref()
+        .ok_or(SgfRenderError::NoOutfile)?;
+    let infiles = expand_inputs(&parsed_args.infiles)?;
+    if infiles.is_empty() {
+        Err(SgfRenderError::NoInputFiles)?;
+    }
+    let mut problems = vec![];
+    let mut answers = vec![];
+    for infile in infiles {
+        for (i, sgf_root) in get_sgf_collection(&Some(infile.clone()))?
+            .iter()
+            .enumerate()
+        {
+            let number = problems.len() + 1;
+            let (problem, answer) = match render_problem(parsed_args, sgf_root, number) {
+                Ok(cells) => cells,
+                Err(e) => {
+                    eprintln!("Failed to render {} game {}", infile.display(), i + 1);
+                    return Err(e);
+                }
+            };
+            problems.push(problem);
+            answers.push(answer);
+        }
+    }
+
+    let mut pages = layout(problems, parsed_args);
+    if parsed_args.book_answers {
+        pages.append(&mut layout(answers, parsed_args));
+    }
+    OutputWriter::new().save_pdf_pages(outfile, &pages)
+}
+
+/// Returns the problem and answer cells for a game.
+fn render_problem(
+    parsed_args: &SgfRenderArgs,
+    sgf_root: &SgfNode,
+    number: usize,
+) -> Result<(Cell, Cell), Box<dyn Error>> {
+    let (problem_node, goban) =
+        find_node(sgf_root, parsed_args.move_number, &parsed_args.node_path)?;
+
+    let mut answer_node = problem_node;
+    let mut answer = goban.clone();
+    let mut to_play = match problem_node.get_property("PL") {
+        Some(SgfProp::PL(color)) => Some(*color),
+        _ => None,
+    };
+    while let Some(child) = answer_node.children().next() {
+        if to_play.is_none() {
+            to_play = match (child.get_property("B"), child.get_property("W")) {
+                (Some(_), _) => Some(sgf_parse::Color::Black),
+                (_, Some(_)) => Some(sgf_parse::Color::White),
+                _ => None,
+            };
+        }
+        answer.process_node(child)?;
+        answer_node = child;
+    }
+
+    let title = match to_play {
+        Some(sgf_parse::Color::Black) => format!("Problem {}: Black to play", number),
+        Some(sgf_parse::Color::White) => format!("Problem {}: White to play", number),
+        None => format!("Problem {}", number),
+    };
+    let problem = Cell {
+        title,
+        comment: comment(problem_node),
+        diagram: lib::make_svg(&goban, &parsed_args.options)?,
+    };
+    let mut answer_options = parsed_args.options.clone();
+    answer_options.move_numbers = Some(goban.move_number + 1..answer.move_number + 1);
+    let answer = Cell {
+        title: format!("Answer {}", number),
+        comment: comment(answer_node),
+        diagram: lib::make_svg(&answer, &answer_options)?,
+    };
+
+    Ok((problem, answer))
+}
+
+fn comment(sgf_node: &SgfNode) -> Option<String> {
+    match sgf_node.get_property("C") {
+        Some(SgfProp::C(text)) => Some(text.to_string()),
+        _ => None,
+    }
+}
+
+/// Places the cells in the grid, starting a new page whenever the grid fills up.
+fn layout(cells: Vec<Cell>, parsed_args: &SgfRenderArgs) -> Vec<Page> {
+    let (width, height) = page_dimensions(parsed_args.page_size);
+    let (columns, rows) = parsed_args.book_grid;
+    let cell_width = (width - 2.0 * PAGE_MARGIN - (columns - 1) as f64 * CELL_GAP) / columns as f64;
+    let cell_height = (height - 2.0 * PAGE_MARGIN - (rows - 1) as f64 * CELL_GAP) / rows as f64;
+    let theme = &parsed_args.options.theme;
+    let scale = caption_scale(cell_width, theme);
+    let caption_height = (1 + COMMENT_LINES) as f64 * theme.caption_line_height * scale;
+
+    let mut pages = vec![];
+    let mut cells = cells.into_iter().peekable();
+    while cells.peek().is_some() {
+        let mut items = vec![];
+        for (i, cell) in cells.by_ref().take(columns * rows).enumerate() {
+            let x = PAGE_MARGIN + (i % columns) as f64 * (cell_width + CELL_GAP);
+            let y = PAGE_MARGIN + (i / columns) as f64 * (cell_height + CELL_GAP);
+            items.push(PageItem {
+                document: caption(&cell, cell_width, caption_height, theme, scale),
+                x,
+                y,
+                width: cell_width,
+                height: caption_height,
+            });
+            items.push(PageItem {
+                document: cell.diagram,
+                x,
+                y: y + caption_height + DIAGRAM_GAP,
+                width: cell_width,
+                height: cell_height - caption_height - DIAGRAM_GAP,
+            });
+        }
+        pages.push(Page {
+            width,
+            height,
+            items,
+        });
+    }
+
+    pages
+}
+
+/// Returns the pixels per board unit for captions: the line spacing of a full 19x19 board
+/// filling the cell, so captions match the size of a diagram caption drawn with the same theme.
+fn caption_scale(cell_width: f64, theme: &Theme) -> f64 {
+    cell_width / (18.0 + 2.0 * theme.board_margin)
+}
+
+/// Draws a cell's title with its comment wrapped underneath.
+fn caption(cell: &Cell, width: f64, height: f64, theme: &Theme, scale: f64) -> Document {
+    let font_size = theme.caption_font_size * scale;
+    let line_height = theme.caption_line_height * scale;
+    let max_columns = (width / (font_size * COLUMN_WIDTH)) as usize;
+    let mut lines = vec![cell.title.clone()];
+    if let Some(comment) = &cell.comment {
+        lines.extend(wrap(comment, max_columns, COMMENT_LINES));
+    }
+    let mut group = element::Group::new()
+        .set("fill", theme.caption_color.as_str())
+        .set("font-size", font_size)
+        .set("font-family", theme.label_font_family.as_str())
+        .set("font-weight", theme.label_font_weight);
+    for (i, line) in lines.iter().enumerate() {
+        group = group.add(
+            element::Text::new()
+                .set("x", 0)
+                .set("y", (i as f64 + 0.75) * line_height)
+                .add(svg::node::Text::new(lib::escape_xml(line))),
+        );
+    }
+
+    Document::new()
+        .set("viewBox", (0.0, 0.0, width, height))
+        .set("width", width)
+        .set("height", height)
+        .add(group)
+}
+
+/// Wraps text at word boundaries to `max_columns` of display width, with wide characters taking
+/// two columns, ending with an ellipsis if it doesn't fit in `max_lines`.
+fn wrap(text: &str, max_columns: usize, max_lines: usize) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.width() + 1 + word.width() > max_columns {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    if lines.len() > max_lines {
+        lines.truncate(max_lines);
+        if let Some(last) = lines.last_mut() {
+            last.push_str("...");
+        }
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_counts_wide_characters_as_two_columns() {
+        assert_eq!(wrap("ab cd ef", 5, 3), vec!["ab cd", "ef"]);
+        assert_eq!(wrap("黒番 白番 黒番", 5, 3), vec!["黒番", "白番", "黒番"]);
+        assert_eq!(wrap("黒番 白番 黒番", 5, 2), vec!["黒番", "白番..."]);
+    }
+}
@@ -1,12 +1,28 @@
 use sgf_parse::{SgfNode, SgfProp};
+use std::collections::hash_map::Entry;
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::ops::Range;
 
+#[derive(Clone, Debug)]
 pub struct Goban {
     pub size: (u8, u8),
     pub stones: HashMap<(u8, u8), StoneColor>,
+    pub stone_move_numbers: HashMap<(u8, u8), u64>,
+    pub moves: Vec<MoveRecord>,
+    pub setup: Vec<SetupRecord>,
+    pub marks: HashMap<(u8, u8), Markup>,
+    pub labels: HashMap<(u8, u8), String>,
+    pub arrows: HashSet<((u8, u8), (u8, u8))>,
+    pub lines: HashSet<((u8, u8), (u8, u8))>,
+    pub dimmed: HashSet<(u8, u8)>,
+    pub view: HashSet<(u8, u8)>,
+    pub black_territory: HashSet<(u8, u8)>,
+    pub white_territory: HashSet<(u8, u8)>,
     pub move_number: u64,
     pub black_captures: u64,
     pub white_captures: u64,
+    /// A title for the diagram, as in a Sensei's Library diagram header.
+    pub title: Option<String>,
 }
 
 impl Goban {
@@ -29,9 +45,21 @@ impl Goban {
         Self {
             size: board_size,
             stones: HashMap::new(),
+            stone_move_numbers: HashMap::new(),
+            moves: vec![],
+            setup: vec![],
+            marks: HashMap::new(),
+            labels: HashMap::new(),
+            arrows: HashSet::new(),
+            lines: HashSet::new(),
+            dimmed: HashSet::new(),
+            view: HashSet::new(),
+            black_territory: HashSet::new(),
+            white_territory: HashSet::new(),
             move_number: 0,
             black_captures: 0,
             white_captures: 0,
+            title: None,
         }
     }
 
@@ -56,6 +84,17 @@ impl Goban {
     }
 
     pub fn process_node(&mut self, sgf_node: &SgfNode) -> Result<(), Box<dyn std::error::Error>> {
+        // Markup only applies to the node it's set on.
+        self.marks.clear();
+        self.labels.clear();
+        self.arrows.clear();
+        self.lines.clear();
+        self.black_territory.clear();
+        self.white_territory.clear();
+        // MN sets the number of the move in this node, so it has to be handled before the move.
+        if let Some(SgfProp::MN(num)) = sgf_node.get_property("MN") {
+            self.set_move_number((*num as u64).saturating_sub(1));
+        }
         for prop in sgf_node.properties() {
             match prop {
                 SgfProp::B(sgf_parse::Move::Move(point)) => {
@@ -70,20 +109,52 @@ impl Goban {
                 }
                 SgfProp::AB(points) => {
                     for point in points.iter() {
-                        self.add_stone(Stone::new(point.x, point.y, StoneColor::Black))?;
+                        self.set_up_point((point.x, point.y), Some(StoneColor::Black))?;
                     }
                 }
                 SgfProp::AW(points) => {
                     for point in points.iter() {
-                        self.add_stone(Stone::new(point.x, point.y, StoneColor::White))?;
+                        self.set_up_point((point.x, point.y), Some(StoneColor::White))?;
                     }
                 }
                 SgfProp::AE(points) => {
                     for point in points.iter() {
-                        self.clear_point((point.x, point.y));
+                        self.set_up_point((point.x, point.y), None)?;
+                    }
+                }
+                SgfProp::CR(points) => self.add_marks(points, Markup::Circle),
+                SgfProp::SQ(points) => self.add_marks(points, Markup::Square),
+                SgfProp::TR(points) => self.add_marks(points, Markup::Triangle),
+                SgfProp::MA(points) => self.add_marks(points, Markup::Cross),
+                SgfProp::SL(points) => self.add_marks(points, Markup::Selected),
+                SgfProp::LB(labels) => {
+                    for (point, text) in labels.iter() {
+                        self.labels.insert((point.x, point.y), text.to_string());
+                    }
+                }
+                SgfProp::AR(pairs) => {
+                    for (start, end) in pairs.iter() {
+                        self.arrows.insert(((start.x, start.y), (end.x, end.y)));
                     }
                 }
-                SgfProp::MN(num) => self.set_move_number(*num as u64),
+                SgfProp::LN(pairs) => {
+                    for (start, end) in pairs.iter() {
+                        self.lines.insert(((start.x, start.y), (end.x, end.y)));
+                    }
+                }
+                SgfProp::TB(points) => {
+                    self.black_territory = points.iter().map(|p| (p.x, p.y)).collect();
+                }
+                SgfProp::TW(points) => {
+                    self.white_territory = points.iter().map(|p| (p.x, p.y)).collect();
+                }
+                // DD and VW are inherited until they're set again. An empty list resets them.
+                SgfProp::DD(points) => {
+                    self.dimmed = points.iter().map(|p| (p.x, p.y)).collect();
+                }
+                SgfProp::VW(points) => {
+                    self.view = points.iter().map(|p| (p.x, p.y)).collect();
+                }
                 _ => {}
             }
         }
@@ -104,6 +175,29 @@ impl Goban {
         Ok(())
     }
 
+    /// Adds or clears a stone with a setup property, recording what was there for `rewind`.
+    fn set_up_point(
+        &mut self,
+        point: (u8, u8),
+        color: Option<StoneColor>,
+    ) -> Result<(), GobanError> {
+        let previous = self
+            .stones
+            .get(&point)
+            .map(|color| (*color, self.stone_move_numbers.get(&point).copied()));
+        match color {
+            Some(color) => self.add_stone(Stone::new(point.0, point.1, color))?,
+            None => self.clear_point(point),
+        }
+        self.setup.push(SetupRecord {
+            moves_played: self.moves.len(),
+            point,
+            previous,
+        });
+
+        Ok(())
+    }
+
     pub fn play_stone(&mut self, stone: Stone) -> Result<(), GobanError> {
         self.add_stone(stone)?;
         let opponent_color = match stone.color {
@@ -112,28 +206,171 @@ impl Goban {
         };
         // Remove any neighboring groups with no liberties.
         let key = (stone.x, stone.y);
+        let mut captures = vec![];
         for neighbor in self.neighbors(key) {
             if let Some(color) = self.stones.get(&neighbor) {
                 if *color == opponent_color {
-                    self.process_captures(&neighbor);
+                    captures.extend(self.process_captures(&neighbor));
                 }
             }
         }
         // Now remove the played stone if still neccessary
-        self.process_captures(&key);
+        captures.extend(self.process_captures(&key));
         self.move_number += 1;
+        if self.stones.contains_key(&key) {
+            self.stone_move_numbers.insert(key, self.move_number);
+        }
+        let (captures, capture_move_numbers) = captures.into_iter().unzip();
+        self.moves.push(MoveRecord {
+            number: self.move_number,
+            stone,
+            captures,
+            capture_move_numbers,
+        });
 
         Ok(())
     }
 
     pub fn clear_point(&mut self, point: (u8, u8)) {
         self.stones.remove(&point);
+        self.stone_move_numbers.remove(&point);
     }
 
     pub fn set_move_number(&mut self, num: u64) {
         self.move_number = num;
     }
 
+    /// Returns the goban as it was before the moves in the range were played.
+    ///
+    /// Setup properties after the first move in the range are undone too, up to the last move in
+    /// the range, or to the end if no moves were played after the range.
+    pub fn rewind(&self, moves: &Range<u64>) -> Goban {
+        let mut goban = self.clone();
+        let positions: Vec<usize> = (0..self.moves.len())
+            .filter(|&i| moves.contains(&self.moves[i].number))
+            .collect();
+        let (first, last) = match (positions.first(), positions.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return goban,
+        };
+        let mut setup = self
+            .setup
+            .iter()
+            .filter(|record| {
+                record.moves_played > first
+                    && (record.moves_played <= last || last + 1 == self.moves.len())
+            })
+            .rev()
+            .peekable();
+        for &i in positions.iter().rev() {
+            while let Some(record) = setup.peek() {
+                if record.moves_played <= i {
+                    break;
+                }
+                goban.clear_point(record.point);
+                if let Some((color, number)) = record.previous {
+                    goban.stones.insert(record.point, color);
+                    if let Some(number) = number {
+                        goban.stone_move_numbers.insert(record.point, number);
+                    }
+                }
+                setup.next();
+            }
+            let record = &self.moves[i];
+            // Captures are restored first since a suicide captures the played stone too.
+            for (stone, number) in record.captures.iter().zip(&record.capture_move_numbers) {
+                goban.stones.insert((stone.x, stone.y), stone.color);
+                if let Some(number) = number {
+                    goban.stone_move_numbers.insert((stone.x, stone.y), *number);
+                }
+            }
+            goban.clear_point((record.stone.x, record.stone.y));
+        }
+
+        goban
+    }
+
+    /// Returns the records of the moves played in the range, in order.
+    pub fn move_records<'a>(
+        &'a self,
+        moves: &'a Range<u64>,
+    ) -> impl DoubleEndedIterator<Item = &'a MoveRecord> {
+        self.moves
+            .iter()
+            .filter(move |record| moves.contains(&record.number))
+    }
+
+    /// Returns a kifu style figure of the moves in `moves`.
+    ///
+    /// The figure shows the position as it stood before the first move in the range, with every
+    /// move in the range laid over it, numbered, even if it was later captured. Moves played on a
+    /// point that's already occupied in the figure are returned as footnotes instead.
+    pub fn figure(&self, moves: &Range<u64>) -> (Goban, Vec<Footnote>) {
+        let mut figure = self.rewind(moves);
+        figure.stone_move_numbers.clear();
+        let mut footnotes = vec![];
+        for record in self.move_records(moves) {
+            let point = (record.stone.x, record.stone.y);
+            match figure.stones.entry(point) {
+                Entry::Occupied(_) => footnotes.push(Footnote {
+                    move_number: record.number,
+                    point,
+                }),
+                Entry::Vacant(entry) => {
+                    entry.insert(record.stone.color);
+                    figure.stone_move_numbers.insert(point, record.number);
+                }
+            }
+        }
+
+        (figure, footnotes)
+    }
+
+    pub fn marks(&self, markup: Markup) -> impl Iterator<Item = (u8, u8)> + '_ {
+        self.marks
+            .iter()
+            .filter(move |(_, m)| **m == markup)
+            .map(|(point, _)| *point)
+    }
+
+    /// Returns the score for `color` based on the TB/TW territory.
+    ///
+    /// Stones of the opposing color inside the territory are counted as dead.
+    pub fn score(&self, color: StoneColor) -> Score {
+        let (territory, opponent_territory, prisoners) = match color {
+            StoneColor::Black => (
+                &self.black_territory,
+                &self.white_territory,
+                self.white_captures,
+            ),
+            StoneColor::White => (
+                &self.white_territory,
+                &self.black_territory,
+                self.black_captures,
+            ),
+        };
+        let dead_stones = territory
+            .iter()
+            .filter(|p| matches!(self.stones.get(p), Some(c) if *c != color))
+            .count() as u64;
+        let live_stones = self
+            .stones
+            .iter()
+            .filter(|(p, c)| **c == color && !opponent_territory.contains(p))
+            .count() as u64;
+
+        Score {
+            territory: territory.len() as u64 + prisoners + dead_stones,
+            area: territory.len() as u64 + live_stones,
+        }
+    }
+
+    fn add_marks(&mut self, points: &HashSet<sgf_parse::Point>, markup: Markup) {
+        for point in points.iter() {
+            self.marks.insert((point.x, point.y), markup);
+        }
+    }
+
     pub fn hoshi_points(&self) -> impl Iterator<Item = &(u8, u8)> {
         match self.size {
             (9, 9) => Self::NINE_HOSHIS.iter(),
@@ -162,10 +399,13 @@ impl Goban {
         neighbors.into_iter()
     }
 
-    fn process_captures(&mut self, start_point: &(u8, u8)) {
+    /// Removes the group at `start_point` if it has no liberties, and returns the removed stones.
+    /// Removes the group at `start_point` if it has no liberties, returning its stones with their
+    /// move numbers.
+    fn process_captures(&mut self, start_point: &(u8, u8)) -> Vec<(Stone, Option<u64>)> {
         let group_color = match self.stones.get(start_point) {
-            Some(color) => color,
-            None => return,
+            Some(color) => *color,
+            None => return vec![],
         };
         let mut group = HashSet::new();
         let mut to_process = VecDeque::new();
@@ -177,8 +417,8 @@ impl Goban {
                     continue;
                 }
                 match self.stones.get(&neighbor) {
-                    None => return,
-                    Some(c) if c == group_color => {
+                    None => return vec![],
+                    Some(c) if *c == group_color => {
                         to_process.push_back(neighbor.clone());
                     }
                     _ => {}
@@ -189,9 +429,14 @@ impl Goban {
             StoneColor::Black => self.black_captures += group.len() as u64,
             StoneColor::White => self.white_captures += group.len() as u64,
         }
-        for stone in group {
-            self.stones.remove(&stone);
+        let mut captures = vec![];
+        for point in group {
+            let number = self.stone_move_numbers.get(&point).copied();
+            self.clear_point(point);
+            captures.push((Stone::new(point.0, point.1, group_color), number));
         }
+
+        captures
     }
 
     fn is_tt_pass(&self, point: &sgf_parse::Point) -> bool {
@@ -205,6 +450,47 @@ pub enum StoneColor {
     White,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Markup {
+    Circle,
+    Square,
+    Triangle,
+    Cross,
+    Selected,
+}
+
+/// A move played on the goban, along with any stones it captured.
+#[derive(Clone, Debug)]
+pub struct MoveRecord {
+    pub number: u64,
+    pub stone: Stone,
+    pub captures: Vec<Stone>,
+    /// The move numbers of the captured stones, for those that had one.
+    pub capture_move_numbers: Vec<Option<u64>>,
+}
+
+/// A point changed by a setup property, with what was on it before.
+#[derive(Clone, Debug)]
+pub struct SetupRecord {
+    /// The number of moves played before the setup.
+    pub moves_played: usize,
+    pub point: (u8, u8),
+    pub previous: Option<(StoneColor, Option<u64>)>,
+}
+
+/// A numbered move that has to be listed below a figure since its point was already occupied.
+#[derive(Copy, Clone, Debug)]
+pub struct Footnote {
+    pub move_number: u64,
+    pub point: (u8, u8),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Score {
+    pub territory: u64,
+    pub area: u64,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Stone {
     pub x: u8,
@@ -244,3 +530,48 @@ impl std::fmt::Display for GobanError {
 }
 
 impl std::error::Error for GobanError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(sgf: &str) -> Goban {
+        let collection = sgf_parse::parse(sgf).unwrap();
+        let mut node = &collection[0];
+        let mut goban = Goban::from_sgf_node(node).unwrap();
+        while let Some(child) = node.children().next() {
+            goban.process_node(child).unwrap();
+            node = child;
+        }
+
+        goban
+    }
+
+    #[test]
+    fn rewind_undoes_setup_inside_the_range() {
+        let goban = play("(;SZ[9]AB[ee];B[aa];AE[ee]AW[bb];W[cc];AB[dd])");
+        let rewound = goban.rewind(&(1..3));
+        assert_eq!(rewound.stones.len(), 1);
+        assert_eq!(rewound.stones.get(&(4, 4)), Some(&StoneColor::Black));
+        assert!(rewound.stone_move_numbers.is_empty());
+    }
+
+    #[test]
+    fn rewind_keeps_setup_after_the_range() {
+        let goban = play("(;SZ[9];B[aa];W[cc];AB[dd];B[ee])");
+        let rewound = goban.rewind(&(1..3));
+        assert_eq!(rewound.stones.len(), 2);
+        assert_eq!(rewound.stones.get(&(3, 3)), Some(&StoneColor::Black));
+        assert_eq!(rewound.stone_move_numbers.get(&(4, 4)), Some(&3));
+    }
+
+    #[test]
+    fn rewind_restores_the_move_numbers_of_captures() {
+        let goban = play("(;SZ[9];B[aa];W[ba];B[ee];W[ab])");
+        assert!(!goban.stones.contains_key(&(0, 0)));
+        let rewound = goban.rewind(&(4..5));
+        assert_eq!(rewound.stones.get(&(0, 0)), Some(&StoneColor::Black));
+        assert_eq!(rewound.stone_move_numbers.get(&(0, 0)), Some(&1));
+        assert!(!rewound.stones.contains_key(&(0, 1)));
+    }
+}
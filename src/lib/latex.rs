@@ -0,0 +1,249 @@
+//! LaTeX diagrams using the igo or psgo packages.
+
+use super::goban::{Goban, Markup, StoneColor};
+use super::{footnote_notes, label_text, GobanSVGError, MakeSvgOptions};
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// Returns the goban as igo macros, ending with a `\showgoban` of the range.
+///
+/// Moves in `options.move_numbers` are numbered, each stone gets its mark or label, and empty
+/// points get theirs with `\gobansymbol`. Moves that can't be shown on the board follow as a line
+/// of text like "14 at 8, 17 at D4".
+pub fn make_igo(goban: &Goban, options: &MakeSvgOptions) -> Result<String, GobanSVGError> {
+    let diagram = Diagram::new(goban, options)?;
+    let size = diagram.goban.size.0.max(diagram.goban.size.1);
+    let mut lines = vec![
+        "\\cleargoban".to_string(),
+        format!("\\gobansize{{{}}}", size),
+    ];
+    let mut annotated = vec![];
+    for &(color, command) in &[(StoneColor::Black, "black"), (StoneColor::White, "white")] {
+        let mut plain = vec![];
+        for (point, stone_color) in diagram.points() {
+            if stone_color != Some(color) {
+                continue;
+            }
+            let coordinate = diagram.igo_coordinate(point);
+            match diagram.annotation(point).map(|a| a.igo()) {
+                Some(annotation) => {
+                    annotated.push(format!("\\{}[{}]{{{}}}", command, annotation, coordinate))
+                }
+                None => plain.push(coordinate),
+            }
+        }
+        if !plain.is_empty() {
+            lines.push(format!("\\{}{{{}}}", command, plain.join(",")));
+        }
+    }
+    lines.append(&mut annotated);
+    for (point, stone_color) in diagram.points() {
+        if stone_color.is_none() {
+            if let Some(annotation) = diagram.annotation(point).map(|a| a.igo()) {
+                lines.push(format!(
+                    "\\gobansymbol{{{}}}{{{}}}",
+                    diagram.igo_coordinate(point),
+                    annotation
+                ));
+            }
+        }
+    }
+    lines.push(format!(
+        "\\showgoban[{},{}]",
+        diagram.igo_coordinate((diagram.x_range.start, diagram.y_range.end - 1)),
+        diagram.igo_coordinate((diagram.x_range.end - 1, diagram.y_range.start)),
+    ));
+    lines.extend(diagram.footnote_line());
+
+    Ok(lines.iter().map(|line| format!("{}\n", line)).collect())
+}
+
+/// Returns the goban as a psgo board environment, cropped to the range.
+///
+/// The starred environments with coordinates are used if labels are on. Moves that can't be shown
+/// on the board follow as a line of text, as with igo.
+pub fn make_psgo(goban: &Goban, options: &MakeSvgOptions) -> Result<String, GobanSVGError> {
+    let diagram = Diagram::new(goban, options)?;
+    let size = diagram.goban.size.0.max(diagram.goban.size.1);
+    let star = if options.render_labels { "*" } else { "" };
+    let full_board = diagram.x_range == (0..diagram.goban.size.0)
+        && diagram.y_range == (0..diagram.goban.size.1);
+    let (begin, end) = if full_board {
+        (
+            format!("\\begin{{psgoboard{}}}[{}]", star, size),
+            format!("\\end{{psgoboard{}}}", star),
+        )
+    } else {
+        let (left, top) = diagram.psgo_numbers((diagram.x_range.start, diagram.y_range.start));
+        let (right, bottom) =
+            diagram.psgo_numbers((diagram.x_range.end - 1, diagram.y_range.end - 1));
+        (
+            format!(
+                "\\begin{{psgopartialboard{}}}[{}]{{({},{})({},{})}}",
+                star, size, left, bottom, right, top
+            ),
+            format!("\\end{{psgopartialboard{}}}", star),
+        )
+    };
+    let mut lines = vec![begin];
+    for (point, stone_color) in diagram.points() {
+        let (x, y) = diagram.psgo_coordinate(point);
+        let annotation = diagram.annotation(point).map(|a| a.psgo());
+        match (stone_color, annotation) {
+            (Some(color), annotation) => {
+                let color = match color {
+                    StoneColor::Black => "black",
+                    StoneColor::White => "white",
+                };
+                let marker = annotation.map(|a| format!("[{}]", a)).unwrap_or_default();
+                lines.push(format!("\\stone{}{{{}}}{{{}}}{{{}}}", marker, color, x, y));
+            }
+            (None, Some(annotation)) => {
+                lines.push(format!("\\markpos{{{}}}{{{}}}{{{}}}", annotation, x, y))
+            }
+            (None, None) => {}
+        }
+    }
+    lines.push(end);
+    lines.extend(diagram.footnote_line());
+
+    Ok(lines.iter().map(|line| format!("{}\n", line)).collect())
+}
+
+/// Text or a mark to draw on a point.
+enum Annotation {
+    Text(String),
+    Mark(Markup),
+}
+
+impl Annotation {
+    fn igo(&self) -> String {
+        match self {
+            Self::Text(text) => text.clone(),
+            Self::Mark(Markup::Circle) => "\\igocircle".to_string(),
+            Self::Mark(Markup::Square) => "\\igosquare".to_string(),
+            Self::Mark(Markup::Triangle) => "\\igotriangle".to_string(),
+            Self::Mark(_) => "\\igocross".to_string(),
+        }
+    }
+
+    fn psgo(&self) -> String {
+        match self {
+            Self::Text(text) => format!("\\marklb{{{}}}", text),
+            Self::Mark(Markup::Circle) => "\\markcr".to_string(),
+            Self::Mark(Markup::Square) => "\\marksq".to_string(),
+            Self::Mark(Markup::Triangle) => "\\marktr".to_string(),
+            Self::Mark(_) => "\\markma".to_string(),
+        }
+    }
+}
+
+/// The figure of a goban to typeset, and the part of it in range.
+struct Diagram<'a> {
+    goban: Cow<'a, Goban>,
+    x_range: Range<u8>,
+    y_range: Range<u8>,
+    move_numbers: Option<Range<u64>>,
+    footnotes: Vec<String>,
+}
+
+impl<'a> Diagram<'a> {
+    fn new(goban: &'a Goban, options: &MakeSvgOptions) -> Result<Self, GobanSVGError> {
+        let (goban, footnotes) = match &options.move_numbers {
+            Some(moves) => {
+                let (figure, footnotes) = goban.figure(moves);
+                let notes = footnote_notes(&figure, &footnotes, &options.coordinates);
+                (Cow::Owned(figure), notes)
+            }
+            None => (Cow::Borrowed(goban), vec![]),
+        };
+        let (x_range, y_range) = options.goban_range.get_ranges(&goban)?;
+        if goban.size.0 > 25 || goban.size.1 > 25 {
+            return Err(GobanSVGError::TooLargeForLatex);
+        }
+
+        Ok(Self {
+            goban,
+            x_range,
+            y_range,
+            move_numbers: options.move_numbers.clone(),
+            footnotes,
+        })
+    }
+
+    /// Returns the footnotes as one line of text, if there are any.
+    fn footnote_line(&self) -> Option<String> {
+        if self.footnotes.is_empty() {
+            None
+        } else {
+            Some(self.footnotes.join(", "))
+        }
+    }
+
+    /// Returns the points in range, top to bottom, with the color of any stone on them.
+    fn points(&self) -> impl Iterator<Item = ((u8, u8), Option<StoneColor>)> + '_ {
+        self.y_range.clone().flat_map(move |y| {
+            self.x_range
+                .clone()
+                .map(move |x| ((x, y), self.goban.stones.get(&(x, y)).copied()))
+        })
+    }
+
+    /// Returns the move number, label or mark to draw on a point, in that order of preference.
+    fn annotation(&self, point: (u8, u8)) -> Option<Annotation> {
+        if let (Some(moves), Some(n)) = (
+            &self.move_numbers,
+            self.goban.stone_move_numbers.get(&point),
+        ) {
+            if moves.contains(n) {
+                return Some(Annotation::Text(n.to_string()));
+            }
+        }
+        if let Some(label) = self.goban.labels.get(&point) {
+            return Some(Annotation::Text(escape(label)));
+        }
+        match self.goban.marks.get(&point) {
+            Some(Markup::Selected) | None => None,
+            Some(&markup) => Some(Annotation::Mark(markup)),
+        }
+    }
+
+    fn igo_coordinate(&self, point: (u8, u8)) -> String {
+        format!(
+            "{}{}",
+            label_text(point.0).to_lowercase(),
+            self.goban.size.1 - point.1
+        )
+    }
+
+    fn psgo_coordinate(&self, point: (u8, u8)) -> (String, u8) {
+        (
+            label_text(point.0).to_lowercase(),
+            self.goban.size.1 - point.1,
+        )
+    }
+
+    /// Returns the column and row of a point, counting from 1 at the bottom left.
+    fn psgo_numbers(&self, point: (u8, u8)) -> (u8, u8) {
+        (point.0 + 1, self.goban.size.1 - point.1)
+    }
+}
+
+/// Escapes LaTeX special characters in label text.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '#' | '$' | '%' | '&' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
@@ -1,105 +1,298 @@
 mod goban;
+mod latex;
+mod sensei;
+mod text;
+mod theme;
 pub use goban::Goban;
+use goban::{Markup, Stone, StoneColor};
+pub use latex::{make_igo, make_psgo};
+pub use sensei::{is_sensei_diagram, make_sensei, parse_sensei, sensei_move_numbers};
+pub use text::{make_ascii, make_unicode};
+pub use theme::Theme;
 
+use std::collections::HashMap;
 use std::ops::Range;
 use svg::node::element;
 
-static BOARD_COLOR: &str = "#cfa87e";
-static BOARD_MARGIN: f64 = 0.64;
+static FADE_DURATION: u64 = 200;
 
-static LABEL_COLOR: &str = "#6e5840";
-static LABEL_FONT_SIZE: f64 = 0.5;
-static LABEL_FONT_FAMILY: &str = "Roboto";
-static LABEL_FONT_WEIGHT: usize = 700;
-static LABEL_MARGIN: f64 = 0.8;
+static TORN_EDGE_TOOTH_WIDTH: f64 = 0.5;
+static TORN_EDGE_DEPTH: f64 = 0.2;
+/// The sides of the board, clockwise from the top.
+static SIDES: [&str; 4] = ["top", "right", "bottom", "left"];
 
-static LINE_COLOR: &str = "black";
-static LINE_WIDTH: f64 = 0.045;
-static HOSHI_RADIUS: f64 = 0.09;
-
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct MakeSvgOptions {
     pub goban_range: GobanRange,
     pub viewbox_width: f64,
     pub render_labels: bool,
+    pub coordinates: Coordinates,
+    pub render_score: bool,
+    pub move_numbers: Option<Range<u64>>,
+    pub animation: Option<SvgAnimation>,
+    pub cropped_edges: CroppedEdges,
+    pub theme: Theme,
+}
+
+/// How to label the lines of the board, and on which sides.
+#[derive(Copy, Clone, Debug)]
+pub struct Coordinates {
+    pub scheme: CoordinateScheme,
+    /// Whether rows count down from the top rather than up from the bottom.
+    pub rows_from_top: bool,
+    /// The sides to label, clockwise from the top.
+    pub sides: [bool; 4],
+}
+
+impl Default for Coordinates {
+    fn default() -> Self {
+        Self {
+            scheme: CoordinateScheme::Letters,
+            rows_from_top: false,
+            sides: [true, false, false, true],
+        }
+    }
+}
+
+impl Coordinates {
+    /// Returns the label for column `x`, or `None` if the scheme has run out of labels.
+    fn column_label(&self, x: u8) -> Option<String> {
+        match self.scheme {
+            CoordinateScheme::Letters if x < 25 => Some(label_text(x)),
+            CoordinateScheme::Letters => None,
+            CoordinateScheme::Sgf => sgf_letter(x),
+            CoordinateScheme::Numbers | CoordinateScheme::Kanji => number_text(x + 1),
+        }
+    }
+
+    /// Returns the label for row `y`, or `None` if the scheme has run out of labels.
+    fn row_label(&self, goban: &Goban, y: u8) -> Option<String> {
+        let row = if self.rows_from_top {
+            y
+        } else {
+            goban.size.1 - 1 - y
+        };
+        match self.scheme {
+            CoordinateScheme::Letters | CoordinateScheme::Numbers => number_text(row + 1),
+            CoordinateScheme::Sgf => sgf_letter(row),
+            CoordinateScheme::Kanji => kanji_text(row + 1),
+        }
+    }
+
+    /// Returns the label for a point, like D4, dp, 4-4 or 4四, falling back to numbers if the
+    /// scheme has run out of labels.
+    fn point_label(&self, goban: &Goban, (x, y): (u8, u8)) -> String {
+        match (self.column_label(x), self.row_label(goban, y)) {
+            (Some(column), Some(row)) if self.scheme != CoordinateScheme::Numbers => {
+                format!("{}{}", column, row)
+            }
+            (Some(column), Some(row)) => format!("{}-{}", column, row),
+            _ => format!("{}-{}", x + 1, goban.size.1 - y),
+        }
+    }
+}
+
+/// Ways of labelling the lines of the board.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CoordinateScheme {
+    /// Columns lettered A-Z skipping I, and numbered rows.
+    Letters,
+    /// Columns and rows lettered as in SGF points, a-z then A-Z.
+    Sgf,
+    /// Numbered columns and rows.
+    Numbers,
+    /// Numbered columns, and rows in Japanese or Chinese numerals.
+    Kanji,
+}
+
+impl CoordinateScheme {
+    /// Whether rows are usually counted down from the top in this scheme.
+    pub fn rows_from_top(self) -> bool {
+        match self {
+            Self::Letters | Self::Numbers => false,
+            Self::Sgf | Self::Kanji => true,
+        }
+    }
+}
+
+/// How to show the sides of a ranged view where the board carries on past the range.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum CroppedEdges {
+    /// Grid lines run on a half space past the last line in range.
+    Lines,
+    /// Grid lines and stones fade out over the half space past the last line in range.
+    Fade,
+    /// The board is torn off along a jagged line.
+    Jagged,
+}
+
+/// Animates the moves in a range, starting from the position before the first one.
+///
+/// Each move appears at its time in `move_times`, in milliseconds, and any stones it captures fade
+/// out. The markup for the goban's node appears at `end`.
+#[derive(Clone, Debug)]
+pub struct SvgAnimation {
+    pub moves: Range<u64>,
+    pub move_times: Vec<u64>,
+    pub end: u64,
 }
 
 pub fn make_svg(goban: &Goban, options: &MakeSvgOptions) -> Result<svg::Document, GobanSVGError> {
+    let theme = &options.theme;
+    // Animations play the moves out, so they number the stones left at the end rather than
+    // rewinding to a figure.
+    let (figure, footnotes) = match (&options.move_numbers, &options.animation) {
+        (Some(moves), None) => {
+            let (figure, footnotes) = goban.figure(moves);
+            (Some(figure), footnotes)
+        }
+        _ => (None, vec![]),
+    };
+    let goban = figure.as_ref().unwrap_or(goban);
     let (x_range, y_range) = options.goban_range.get_ranges(goban)?;
     let width = x_range.end - x_range.start;
     let height = y_range.end - y_range.start;
-    let label_margin = if options.render_labels {
-        if width > 25 || height > 99 {
-            return Err(GobanSVGError::UnlabellableRange);
+    let labels = if options.render_labels {
+        Some(coordinate_labels(
+            goban,
+            &x_range,
+            &y_range,
+            &options.coordinates,
+        )?)
+    } else {
+        None
+    };
+    let mut label_margins = [0.0; 4];
+    if let Some((_, row_labels)) = &labels {
+        // The label margin fits two digits, and the sides widen for anything wider.
+        let widest = row_labels
+            .iter()
+            .map(|label| label_width(label))
+            .fold(0.0, f64::max);
+        let extra = theme.label_font_size * (widest - label_width("00")).max(0.0);
+        for (i, (margin, &side)) in label_margins
+            .iter_mut()
+            .zip(&options.coordinates.sides)
+            .enumerate()
+        {
+            if side {
+                *margin = theme.label_margin + if i % 2 == 1 { extra } else { 0.0 };
+            }
         }
-        LABEL_MARGIN
+    }
+    let [top_margin, right_margin, bottom_margin, left_margin] = label_margins;
+
+    let cropped = [
+        y_range.start > 0,
+        x_range.end < goban.size.0,
+        y_range.end < goban.size.1,
+        x_range.start > 0,
+    ];
+    let outline = board_outline(
+        &x_range,
+        &y_range,
+        cropped,
+        options.cropped_edges == CroppedEdges::Jagged,
+    );
+    // Torn edges stick out past the crop, so the board needs room for their teeth.
+    let board_margin = if options.cropped_edges == CroppedEdges::Jagged && cropped.contains(&true) {
+        theme
+            .board_margin
+            .max(0.5 + TORN_EDGE_DEPTH + theme.line_width)
     } else {
-        0.0
+        theme.board_margin
     };
+    let mut definitions = {
+        let clip_path = element::ClipPath::new().set("id", "board-clip");
+        let clip_path = match options.cropped_edges {
+            CroppedEdges::Jagged if cropped.contains(&true) => {
+                // Each side ends on the corner the next side starts from.
+                let points = outline.iter().flat_map(|side| &side[1..]);
+                clip_path.add(element::Polygon::new().set("points", svg_points(points)))
+            }
+            _ => clip_path.add(
+                element::Rectangle::new()
+                    .set("x", x_range.start as f64 - 0.5)
+                    .set("y", y_range.start as f64 - 0.5)
+                    .set("width", width as f64)
+                    .set("height", height as f64),
+            ),
+        };
+        let black_stone_fill = stone_gradient("black-stone-fill", &theme.black_stone_gradient);
+        let white_stone_fill = stone_gradient("white-stone-fill", &theme.white_stone_gradient);
 
-    let definitions = {
-        let clip_path = element::ClipPath::new().set("id", "board-clip").add(
-            element::Rectangle::new()
-                .set("x", x_range.start as f64 - 0.5)
-                .set("y", y_range.start as f64 - 0.5)
-                .set("width", width as f64)
-                .set("height", height as f64),
-        );
-        let black_stone_fill = element::RadialGradient::new()
-            .set("id", "black-stone-fill")
-            .set("cx", "35%")
-            .set("cy", "35%")
+        let arrowhead = element::Marker::new()
+            .set("id", "arrowhead")
+            .set("viewBox", (0, 0, 10, 10))
+            .set("refX", 10)
+            .set("refY", 5)
+            .set("markerWidth", theme.arrowhead_size)
+            .set("markerHeight", theme.arrowhead_size)
+            .set("orient", "auto")
             .add(
-                element::Stop::new()
-                    .set("offset", "0%")
-                    .set("stop-color", "#666"),
-            )
-            .add(
-                element::Stop::new()
-                    .set("offset", "100%")
-                    .set("stop-color", "black"),
-            );
-        let white_stone_fill = element::RadialGradient::new()
-            .set("id", "white-stone-fill")
-            .set("cx", "35%")
-            .set("cy", "35%")
-            .add(
-                element::Stop::new()
-                    .set("offset", "0%")
-                    .set("stop-color", "#ddd"),
-            )
-            .add(
-                element::Stop::new()
-                    .set("offset", "30%")
-                    .set("stop-color", "#bbb"),
-            )
-            .add(
-                element::Stop::new()
-                    .set("offset", "100%")
-                    .set("stop-color", "#9a9a9a"),
+                element::Path::new()
+                    .set("d", "M 0 0 L 10 5 L 0 10 z")
+                    .set("fill", theme.arrow_color.as_str()),
             );
 
         element::Definitions::new()
             .add(clip_path)
+            .add(label_mask(goban, theme))
             .add(black_stone_fill)
             .add(white_stone_fill)
+            .add(arrowhead)
     };
-    let board_width = width as f64 - 1.0 + 2.0 * BOARD_MARGIN + label_margin;
-    let board_height = height as f64 - 1.0 + 2.0 * BOARD_MARGIN + label_margin;
+    if options.cropped_edges == CroppedEdges::Fade {
+        for (i, side) in SIDES.iter().enumerate() {
+            if cropped[i] {
+                let (gradient, mask) = fade_mask(side, &outline[i], &x_range, &y_range);
+                definitions = definitions.add(gradient).add(mask);
+            }
+        }
+    }
+    let board_width = width as f64 - 1.0 + 2.0 * board_margin + left_margin + right_margin;
+    let mut caption = footnote_lines(goban, &footnotes, &options.coordinates, board_width, theme);
+    if options.render_score {
+        for &(name, color) in &[("Black", StoneColor::Black), ("White", StoneColor::White)] {
+            let score = goban.score(color);
+            caption.push(format!(
+                "{}: {} territory, {} area",
+                name, score.territory, score.area
+            ));
+        }
+    }
+    let caption_height = caption.len() as f64 * theme.caption_line_height;
+    let board_height =
+        height as f64 - 1.0 + 2.0 * board_margin + top_margin + bottom_margin + caption_height;
 
     let diagram = {
-        let board = draw_board(goban).set("clip-path", "url(#board-clip)");
+        let mut board =
+            draw_board(goban, options, &x_range, &y_range).set("clip-path", "url(#board-clip)");
+        if options.cropped_edges == CroppedEdges::Fade {
+            for (i, side) in SIDES.iter().enumerate() {
+                if cropped[i] {
+                    board = element::Group::new()
+                        .set("mask", format!("url(#crop-fade-{})", side))
+                        .add(board);
+                }
+            }
+        }
         let board_view = {
-            let offset = BOARD_MARGIN + label_margin;
             let board_view_transform = format!(
                 "translate({}, {})",
-                offset - x_range.start as f64,
-                offset - y_range.start as f64
+                board_margin + left_margin - x_range.start as f64,
+                board_margin + top_margin - y_range.start as f64
             );
-            element::Group::new()
+            let mut board_view = element::Group::new()
                 .set("id", "board-view")
                 .add(board)
-                .set("transform", board_view_transform)
+                .set("transform", board_view_transform);
+            if options.cropped_edges == CroppedEdges::Jagged && cropped.contains(&true) {
+                board_view = board_view.add(draw_torn_edges(
+                    &outline, cropped, &x_range, &y_range, theme,
+                ));
+            }
+            board_view
         };
 
         let scale = options.viewbox_width / board_width;
@@ -110,10 +303,23 @@ pub fn make_svg(goban: &Goban, options: &MakeSvgOptions) -> Result<svg::Document
             .add(board_view)
             .set("transform", transform);
 
-        if options.render_labels {
+        if let Some((column_labels, row_labels)) = &labels {
             diagram = diagram.add(draw_labels(
-                x_range,
-                goban.size.1 - height - y_range.start + 1..goban.size.1 - y_range.start + 1,
+                column_labels,
+                row_labels,
+                options.coordinates.sides,
+                (left_margin, top_margin),
+                board_margin,
+                theme,
+            ));
+        }
+
+        if !caption.is_empty() {
+            diagram = diagram.add(draw_caption(
+                &caption,
+                board_width,
+                board_height - caption_height,
+                theme,
             ));
         }
 
@@ -125,7 +331,7 @@ pub fn make_svg(goban: &Goban, options: &MakeSvgOptions) -> Result<svg::Document
         .set("y", 0)
         .set("width", "100%")
         .set("height", "100%")
-        .set("fill", BOARD_COLOR);
+        .set("fill", theme.board_color.as_str());
 
     let viewbox_height = options.viewbox_width * board_height / board_width;
     Ok(svg::Document::new()
@@ -136,46 +342,210 @@ pub fn make_svg(goban: &Goban, options: &MakeSvgOptions) -> Result<svg::Document
         .add(diagram))
 }
 
+/// Returns the outline of the board view as four sides, clockwise from the top left corner.
+///
+/// With `torn` set, the sides where the board is cropped zigzag outwards, so that the teeth
+/// never reach the stones on the last lines shown.
+fn board_outline(
+    x_range: &Range<u8>,
+    y_range: &Range<u8>,
+    cropped: [bool; 4],
+    torn: bool,
+) -> [Vec<(f64, f64)>; 4] {
+    let (left, top) = (x_range.start as f64 - 0.5, y_range.start as f64 - 0.5);
+    let (right, bottom) = (x_range.end as f64 - 0.5, y_range.end as f64 - 0.5);
+    let corners = [(left, top), (right, top), (right, bottom), (left, bottom)];
+    let outwards = [(0.0, -1.0), (1.0, 0.0), (0.0, 1.0), (-1.0, 0.0)];
+    let mut sides: [Vec<(f64, f64)>; 4] = Default::default();
+    for (i, side) in sides.iter_mut().enumerate() {
+        let (start, end) = (corners[i], corners[(i + 1) % 4]);
+        let length = (end.0 - start.0).abs() + (end.1 - start.1).abs();
+        let torn = torn && cropped[i];
+        // An even number of steps, so that the side ends back out at the corner.
+        let steps = if torn {
+            2 * (length / TORN_EDGE_TOOTH_WIDTH).round().max(1.0) as usize
+        } else {
+            1
+        };
+        for step in 0..=steps {
+            let t = step as f64 / steps as f64;
+            let depth = if torn && step % 2 == 1 {
+                TORN_EDGE_DEPTH
+            } else {
+                0.0
+            };
+            side.push((
+                start.0 + (end.0 - start.0) * t + outwards[i].0 * depth,
+                start.1 + (end.1 - start.1) * t + outwards[i].1 * depth,
+            ));
+        }
+    }
+
+    sides
+}
+
+/// Formats points for a polygon or polyline.
+fn svg_points<'a>(points: impl Iterator<Item = &'a (f64, f64)>) -> String {
+    points
+        .map(|(x, y)| format!("{},{}", x, y))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Returns a mask fading out the half space inside one side of the outline, with its gradient.
+fn fade_mask(
+    side: &str,
+    outline: &[(f64, f64)],
+    x_range: &Range<u8>,
+    y_range: &Range<u8>,
+) -> (element::LinearGradient, element::Mask) {
+    let (start, end) = (outline[0], outline[outline.len() - 1]);
+    // The outline runs clockwise, so the inside is to the right of each side.
+    let length = (end.0 - start.0).abs() + (end.1 - start.1).abs();
+    let inwards = ((start.1 - end.1) / length, (end.0 - start.0) / length);
+    let gradient_id = format!("crop-fade-{}-gradient", side);
+    let gradient = element::LinearGradient::new()
+        .set("id", gradient_id.as_str())
+        .set("gradientUnits", "userSpaceOnUse")
+        .set("x1", start.0)
+        .set("y1", start.1)
+        .set("x2", start.0 + inwards.0 * 0.5)
+        .set("y2", start.1 + inwards.1 * 0.5)
+        .add(
+            element::Stop::new()
+                .set("offset", "0%")
+                .set("stop-color", "black"),
+        )
+        .add(
+            element::Stop::new()
+                .set("offset", "100%")
+                .set("stop-color", "white"),
+        );
+    let (x, y) = (x_range.start as f64 - 0.5, y_range.start as f64 - 0.5);
+    let (width, height) = (
+        (x_range.end - x_range.start) as f64,
+        (y_range.end - y_range.start) as f64,
+    );
+    let mask = element::Mask::new()
+        .set("id", format!("crop-fade-{}", side))
+        .set("maskUnits", "userSpaceOnUse")
+        .set("x", x)
+        .set("y", y)
+        .set("width", width)
+        .set("height", height)
+        .add(
+            element::Rectangle::new()
+                .set("x", x)
+                .set("y", y)
+                .set("width", width)
+                .set("height", height)
+                .set("fill", format!("url(#{})", gradient_id)),
+        );
+
+    (gradient, mask)
+}
+
+/// Draws the jagged lines along the sides where the board is torn off.
+///
+/// The lines stop at the edge lines of any real sides of the board they meet.
+fn draw_torn_edges(
+    outline: &[Vec<(f64, f64)>; 4],
+    cropped: [bool; 4],
+    x_range: &Range<u8>,
+    y_range: &Range<u8>,
+    theme: &Theme,
+) -> element::Group {
+    let bound = |is_cropped: bool, line: u8, overhang: f64| {
+        if is_cropped {
+            line as f64 + overhang
+        } else {
+            line as f64
+        }
+    };
+    let (top, bottom) = (
+        bound(cropped[0], y_range.start, -0.5 - TORN_EDGE_DEPTH),
+        bound(cropped[2], y_range.end - 1, 0.5 + TORN_EDGE_DEPTH),
+    );
+    let (left, right) = (
+        bound(cropped[3], x_range.start, -0.5 - TORN_EDGE_DEPTH),
+        bound(cropped[1], x_range.end - 1, 0.5 + TORN_EDGE_DEPTH),
+    );
+    let mut edges = element::Group::new()
+        .set("id", "torn-edges")
+        .set("fill", "none")
+        .set("stroke", theme.line_color.as_str())
+        .set("stroke-width", theme.line_width)
+        .set("stroke-linejoin", "round");
+    for (side, &is_cropped) in outline.iter().zip(cropped.iter()) {
+        if is_cropped {
+            let points = side
+                .iter()
+                .filter(|(x, y)| (left..=right).contains(x) && (top..=bottom).contains(y));
+            edges = edges.add(element::Polyline::new().set("points", svg_points(points)));
+        }
+    }
+
+    edges
+}
+
 /// Draws a goban of with squares of unit size.
-fn draw_board(goban: &Goban) -> element::Group {
-    // TODO: Add support for markup and comments
+fn draw_board(
+    goban: &Goban,
+    options: &MakeSvgOptions,
+    x_range: &Range<u8>,
+    y_range: &Range<u8>,
+) -> element::Group {
+    let theme = &options.theme;
+    // TODO: Add support for comments
     let mut lines = element::Group::new()
         .set("id", "lines")
-        .set("stroke", LINE_COLOR)
-        .set("stroke-width", LINE_WIDTH)
-        .set("stroke-linecap", "square");
+        .set("stroke", theme.line_color.as_str())
+        .set("stroke-width", theme.line_width)
+        .set("stroke-linecap", "square")
+        .set("mask", "url(#label-mask)");
 
-    // Draw lines
-    for x in 0..goban.size.0 as usize {
-        lines = lines.add(
-            element::Line::new()
-                .set("x1", x)
-                .set("y1", 0)
-                .set("x2", x)
-                .set("y2", goban.size.1 - 1),
-        );
+    // Draw lines, with the edges of the board on top in their own group. Edges are only drawn
+    // if they're in range, so a cropped side never looks like the edge of the board.
+    let mut edges = element::Group::new()
+        .set("id", "edge-lines")
+        .set("stroke-width", theme.edge_line_width);
+    for x in 0..goban.size.0 {
+        let line = element::Line::new()
+            .set("x1", x)
+            .set("y1", 0)
+            .set("x2", x)
+            .set("y2", goban.size.1 - 1);
+        if x != 0 && x != goban.size.0 - 1 {
+            lines = lines.add(line);
+        } else if x_range.contains(&x) {
+            edges = edges.add(line);
+        }
     }
-    for y in 0..goban.size.1 as usize {
-        lines = lines.add(
-            element::Line::new()
-                .set("x1", 0)
-                .set("y1", y)
-                .set("x2", goban.size.0 - 1)
-                .set("y2", y),
-        );
+    for y in 0..goban.size.1 {
+        let line = element::Line::new()
+            .set("x1", 0)
+            .set("y1", y)
+            .set("x2", goban.size.0 - 1)
+            .set("y2", y);
+        if y != 0 && y != goban.size.1 - 1 {
+            lines = lines.add(line);
+        } else if y_range.contains(&y) {
+            edges = edges.add(line);
+        }
     }
+    lines = lines.add(edges);
 
     // Draw hoshi
     let mut hoshi = element::Group::new()
         .set("id", "hoshi")
         .set("stroke", "none")
-        .set("fill", LINE_COLOR);
+        .set("fill", theme.line_color.as_str());
     for &(x, y) in goban.hoshi_points() {
         hoshi = hoshi.add(
             element::Circle::new()
                 .set("cx", x)
                 .set("cy", y)
-                .set("r", HOSHI_RADIUS),
+                .set("r", theme.hoshi_radius),
         );
     }
     lines = lines.add(hoshi);
@@ -184,76 +554,563 @@ fn draw_board(goban: &Goban) -> element::Group {
     let mut stones = element::Group::new()
         .set("id", "stones")
         .set("stroke", "none");
-    for stone in goban.stones() {
-        // Draw the shadow
-        stones = stones.add(
+    let mut overlays = element::Group::new()
+        .set("id", "overlays")
+        .add(draw_move_numbers(goban, options))
+        .add(draw_territory(goban, theme))
+        .add(draw_markup(goban, theme))
+        .add(draw_dimmed(goban, theme));
+    match &options.animation {
+        Some(animation) => {
+            stones = draw_animated_stones(goban, animation, theme);
+            overlays = overlays
+                .set("opacity", 0)
+                .add(fade_animation(0, 1, animation.end));
+        }
+        None => {
+            for stone in goban.stones() {
+                for circle in stone_circles(stone, theme) {
+                    stones = stones.add(circle);
+                }
+            }
+        }
+    }
+
+    element::Group::new()
+        .set("id", "goban")
+        .add(lines)
+        .add(stones)
+        .add(overlays)
+}
+
+/// Returns the circles drawing a stone, with its shadow first if the theme has shadows.
+fn stone_circles(stone: Stone, theme: &Theme) -> Vec<element::Circle> {
+    let mut circles = vec![];
+    // Stones with shadows are nudged up and left, as if lit from there.
+    let offset = if theme.stone_shadow_opacity > 0.0 {
+        circles.push(
             element::Circle::new()
                 .set("cx", stone.x as f64 + 0.025)
                 .set("cy", stone.y as f64 + 0.025)
-                .set("r", 0.475)
+                .set("r", theme.stone_radius)
                 .set("fill", "black")
-                .set("fill-opacity", 0.5),
+                .set("fill-opacity", theme.stone_shadow_opacity),
         );
-        let fill = match stone.color {
-            goban::StoneColor::Black => "url(#black-stone-fill)",
-            goban::StoneColor::White => "url(#white-stone-fill)",
-        };
-        // Draw the stone
-        stones = stones.add(
+        -0.017
+    } else {
+        0.0
+    };
+    let fill = match stone.color {
+        StoneColor::Black => "url(#black-stone-fill)",
+        StoneColor::White => "url(#white-stone-fill)",
+    };
+    let mut circle = element::Circle::new()
+        .set("cx", stone.x as f64 + offset)
+        .set("cy", stone.y as f64 + offset)
+        .set("r", theme.stone_radius)
+        .set("fill", fill);
+    if theme.stone_outline_width > 0.0 {
+        circle = circle
+            .set("stroke", theme.stone_outline_color.as_str())
+            .set("stroke-width", theme.stone_outline_width);
+    }
+    circles.push(circle);
+
+    circles
+}
+
+/// Returns a radial gradient for stones, lit from the top left.
+fn stone_gradient(id: &str, stops: &[(f64, String)]) -> element::RadialGradient {
+    let mut gradient = element::RadialGradient::new()
+        .set("id", id)
+        .set("cx", "35%")
+        .set("cy", "35%");
+    for (offset, color) in stops {
+        gradient = gradient.add(
+            element::Stop::new()
+                .set("offset", format!("{}%", offset))
+                .set("stop-color", color.as_str()),
+        );
+    }
+
+    gradient
+}
+
+/// Draws the stones for an animation.
+fn draw_animated_stones(goban: &Goban, animation: &SvgAnimation, theme: &Theme) -> element::Group {
+    // Each stone with the times it appears and is captured, if it does either.
+    let mut timeline: Vec<(Stone, Option<u64>, Option<u64>)> = goban
+        .rewind(&animation.moves)
+        .stones()
+        .map(|stone| (stone, None, None))
+        .collect();
+    let mut on_board: HashMap<(u8, u8), usize> = timeline
+        .iter()
+        .enumerate()
+        .map(|(i, (stone, _, _))| ((stone.x, stone.y), i))
+        .collect();
+    let records = goban.move_records(&animation.moves);
+    for (record, &time) in records.zip(animation.move_times.iter()) {
+        on_board.insert((record.stone.x, record.stone.y), timeline.len());
+        timeline.push((record.stone, Some(time), None));
+        for stone in record.captures.iter() {
+            if let Some(j) = on_board.remove(&(stone.x, stone.y)) {
+                timeline[j].2 = Some(time);
+            }
+        }
+    }
+
+    let mut stones = element::Group::new()
+        .set("id", "stones")
+        .set("stroke", "none");
+    for (stone, appears, captured) in timeline {
+        let mut group = element::Group::new();
+        for circle in stone_circles(stone, theme) {
+            group = group.add(circle);
+        }
+        if let Some(time) = appears {
+            group = group.set("opacity", 0).add(fade_animation(0, 1, time));
+        }
+        if let Some(time) = captured {
+            group = group.add(fade_animation(1, 0, time));
+        }
+        stones = stones.add(group);
+    }
+
+    stones
+}
+
+/// Returns an animation fading an element's opacity starting at `begin` milliseconds.
+fn fade_animation(from: u8, to: u8, begin: u64) -> element::Animate {
+    element::Animate::new()
+        .set("attributeName", "opacity")
+        .set("from", from)
+        .set("to", to)
+        .set("begin", format!("{}ms", begin))
+        .set("dur", format!("{}ms", FADE_DURATION))
+        .set("fill", "freeze")
+}
+
+/// Draws the move numbers on any stones played in the range set in `options`.
+fn draw_move_numbers(goban: &Goban, options: &MakeSvgOptions) -> element::Group {
+    let theme = &options.theme;
+    let mut move_numbers = element::Group::new()
+        .set("id", "move-numbers")
+        .set("font-size", theme.label_font_size)
+        .set("font-family", theme.label_font_family.as_str())
+        .set("font-weight", theme.label_font_weight)
+        .set("text-anchor", "middle")
+        .set("dominant-baseline", "middle");
+    let range = match &options.move_numbers {
+        Some(range) => range,
+        None => return move_numbers,
+    };
+    for (&(x, y), &n) in goban.stone_move_numbers.iter() {
+        if range.contains(&n) {
+            move_numbers = move_numbers.add(
+                element::Text::new()
+                    .set("x", x)
+                    .set("y", y)
+                    .set("fill", markup_color(goban, theme, (x, y)))
+                    .add(svg::node::Text::new(n.to_string())),
+            );
+        }
+    }
+
+    move_numbers
+}
+
+/// Draws small squares on the TB/TW territory points.
+fn draw_territory(goban: &Goban, theme: &Theme) -> element::Group {
+    let mut territory = element::Group::new()
+        .set("id", "territory")
+        .set("stroke", "none");
+    for &(id, color, points) in &[
+        ("black-territory", "black", &goban.black_territory),
+        ("white-territory", "white", &goban.white_territory),
+    ] {
+        let mut group = element::Group::new().set("id", id).set("fill", color);
+        for &(x, y) in points.iter() {
+            group = group.add(
+                element::Rectangle::new()
+                    .set("x", x as f64 - theme.territory_size / 2.0)
+                    .set("y", y as f64 - theme.territory_size / 2.0)
+                    .set("width", theme.territory_size)
+                    .set("height", theme.territory_size),
+            );
+        }
+        territory = territory.add(group);
+    }
+
+    territory
+}
+
+/// Draws the markup (CR, SQ, TR, MA, SL, LB, LN and AR) for the goban, with one group per
+/// markup type.
+fn draw_markup(goban: &Goban, theme: &Theme) -> element::Group {
+    let mut circles = element::Group::new()
+        .set("id", "circles")
+        .set("fill", "none")
+        .set("stroke-width", theme.markup_width);
+    for (x, y) in goban.marks(Markup::Circle) {
+        circles = circles.add(
             element::Circle::new()
-                .set("cx", stone.x as f64 - 0.017)
-                .set("cy", stone.y as f64 - 0.017)
-                .set("r", 0.475)
-                .set("fill", fill),
+                .set("cx", x)
+                .set("cy", y)
+                .set("r", 0.25)
+                .set("stroke", markup_color(goban, theme, (x, y))),
+        );
+    }
+
+    let mut squares = element::Group::new()
+        .set("id", "squares")
+        .set("fill", "none")
+        .set("stroke-width", theme.markup_width);
+    for (x, y) in goban.marks(Markup::Square) {
+        squares = squares.add(
+            element::Rectangle::new()
+                .set("x", x as f64 - 0.25)
+                .set("y", y as f64 - 0.25)
+                .set("width", 0.5)
+                .set("height", 0.5)
+                .set("stroke", markup_color(goban, theme, (x, y))),
+        );
+    }
+
+    let mut triangles = element::Group::new()
+        .set("id", "triangles")
+        .set("fill", "none")
+        .set("stroke-width", theme.markup_width)
+        .set("stroke-linejoin", "round");
+    for (x, y) in goban.marks(Markup::Triangle) {
+        let (fx, fy) = (x as f64, y as f64);
+        let points = format!(
+            "{},{} {},{} {},{}",
+            fx,
+            fy - 0.3,
+            fx - 0.26,
+            fy + 0.15,
+            fx + 0.26,
+            fy + 0.15
+        );
+        triangles = triangles.add(
+            element::Polygon::new()
+                .set("points", points)
+                .set("stroke", markup_color(goban, theme, (x, y))),
+        );
+    }
+
+    let mut crosses = element::Group::new()
+        .set("id", "crosses")
+        .set("fill", "none")
+        .set("stroke-width", theme.markup_width)
+        .set("stroke-linecap", "round");
+    for (x, y) in goban.marks(Markup::Cross) {
+        let (fx, fy) = (x as f64, y as f64);
+        let data = element::path::Data::new()
+            .move_to((fx - 0.2, fy - 0.2))
+            .line_to((fx + 0.2, fy + 0.2))
+            .move_to((fx + 0.2, fy - 0.2))
+            .line_to((fx - 0.2, fy + 0.2));
+        crosses = crosses.add(
+            element::Path::new()
+                .set("d", data)
+                .set("stroke", markup_color(goban, theme, (x, y))),
+        );
+    }
+
+    let mut selected = element::Group::new()
+        .set("id", "selected")
+        .set("stroke", "none")
+        .set("fill-opacity", theme.selected_opacity);
+    for (x, y) in goban.marks(Markup::Selected) {
+        selected = selected.add(
+            element::Rectangle::new()
+                .set("x", x as f64 - 0.5)
+                .set("y", y as f64 - 0.5)
+                .set("width", 1.0)
+                .set("height", 1.0)
+                .set("fill", markup_color(goban, theme, (x, y))),
+        );
+    }
+
+    let mut labels = element::Group::new()
+        .set("id", "labels")
+        .set("font-size", theme.label_font_size)
+        .set("font-family", theme.label_font_family.as_str())
+        .set("font-weight", theme.label_font_weight)
+        .set("text-anchor", "middle")
+        .set("dominant-baseline", "middle");
+    for (&(x, y), text) in goban.labels.iter() {
+        labels = labels.add(
+            element::Text::new()
+                .set("x", x)
+                .set("y", y)
+                .set("fill", markup_color(goban, theme, (x, y)))
+                .add(svg::node::Text::new(escape_xml(text))),
+        );
+    }
+
+    let mut lines = element::Group::new()
+        .set("id", "markup-lines")
+        .set("stroke", theme.arrow_color.as_str())
+        .set("stroke-width", theme.markup_width)
+        .set("stroke-linecap", "round");
+    for &(start, end) in goban.lines.iter() {
+        lines = lines.add(
+            element::Line::new()
+                .set("x1", start.0)
+                .set("y1", start.1)
+                .set("x2", end.0)
+                .set("y2", end.1),
+        );
+    }
+
+    let mut arrows = element::Group::new()
+        .set("id", "arrows")
+        .set("stroke", theme.arrow_color.as_str())
+        .set("stroke-width", theme.markup_width)
+        .set("stroke-linecap", "round");
+    for &(start, end) in goban.arrows.iter() {
+        arrows = arrows.add(
+            element::Line::new()
+                .set("x1", start.0)
+                .set("y1", start.1)
+                .set("x2", end.0)
+                .set("y2", end.1)
+                .set("marker-end", "url(#arrowhead)"),
         );
     }
 
     element::Group::new()
-        .set("id", "goban")
+        .set("id", "markup")
+        .add(selected)
+        .add(circles)
+        .add(squares)
+        .add(triangles)
+        .add(crosses)
+        .add(labels)
         .add(lines)
-        .add(stones)
+        .add(arrows)
 }
 
-/// Draw labels for the provided ranges.
-///
-/// Assumes lines are a unit apart, offset by BOARD_MARGIN.
-/// Respects LABEL_MARGIN.
-fn draw_labels(x_range: Range<u8>, y_range: Range<u8>) -> element::Group {
-    let mut row_labels = element::Group::new().set("text-anchor", "middle");
-    let start = x_range.start;
-    for x in x_range {
-        let text = svg::node::Text::new(label_text(x));
-        row_labels = row_labels.add(
-            element::Text::new()
-                .set("x", (x - start) as f64 + BOARD_MARGIN)
-                .set("y", 0.0)
-                .add(text),
+/// Builds a mask which hides the lines under any labels on empty points.
+fn label_mask(goban: &Goban, theme: &Theme) -> element::Mask {
+    let mut mask = element::Mask::new()
+        .set("id", "label-mask")
+        .set("maskUnits", "userSpaceOnUse")
+        .set("x", -1)
+        .set("y", -1)
+        .set("width", goban.size.0 + 1)
+        .set("height", goban.size.1 + 1)
+        .add(
+            element::Rectangle::new()
+                .set("x", -1)
+                .set("y", -1)
+                .set("width", goban.size.0 + 1)
+                .set("height", goban.size.1 + 1)
+                .set("fill", "white"),
+        );
+    for point in goban.labels.keys() {
+        if !goban.stones.contains_key(point) {
+            mask = mask.add(
+                element::Circle::new()
+                    .set("cx", point.0)
+                    .set("cy", point.1)
+                    .set("r", theme.label_mask_radius)
+                    .set("fill", "black"),
+            );
+        }
+    }
+
+    mask
+}
+
+/// Greys out the DD points by covering them with a translucent layer of board color.
+fn draw_dimmed(goban: &Goban, theme: &Theme) -> element::Group {
+    let mut dimmed = element::Group::new()
+        .set("id", "dimmed")
+        .set("stroke", "none")
+        .set("fill", theme.board_color.as_str())
+        .set("fill-opacity", theme.dimmed_opacity);
+    for &(x, y) in goban.dimmed.iter() {
+        dimmed = dimmed.add(
+            element::Rectangle::new()
+                .set("x", x as f64 - 0.5)
+                .set("y", y as f64 - 0.5)
+                .set("width", 1.0)
+                .set("height", 1.0),
         );
     }
-    let mut column_labels = element::Group::new()
-        .set("dominant-baseline", "middle")
-        .set("text-anchor", "end");
-    let end = y_range.end;
-    for y in y_range {
-        let text = svg::node::Text::new(y.to_string());
-        column_labels = column_labels.add(
+
+    dimmed
+}
+
+/// Returns a color which will contrast with whatever is at the given point.
+fn markup_color<'a>(goban: &Goban, theme: &'a Theme, point: (u8, u8)) -> &'a str {
+    match goban.stones.get(&point) {
+        Some(StoneColor::Black) => theme.markup_color_on_black.as_str(),
+        Some(StoneColor::White) => theme.markup_color_on_white.as_str(),
+        None => theme.markup_color_on_board.as_str(),
+    }
+}
+
+/// Draw the column labels along the top and bottom and the row labels down the left and right
+/// sides, on those of the `sides` that are set.
+///
+/// Assumes lines are a unit apart, offset by `board_margin`.
+/// Respects theme.label_margin, with `offset` being the margins to the left and above the board.
+fn draw_labels(
+    column_labels: &[String],
+    row_labels: &[String],
+    sides: [bool; 4],
+    offset: (f64, f64),
+    board_margin: f64,
+    theme: &Theme,
+) -> element::Group {
+    let [top, right, bottom, left] = sides;
+    let board_right = column_labels.len() as f64 - 1.0 + 2.0 * board_margin;
+    let board_bottom = row_labels.len() as f64 - 1.0 + 2.0 * board_margin;
+    let column_group = |y: f64, baseline: &str| {
+        let mut group = element::Group::new().set("text-anchor", "middle");
+        if !baseline.is_empty() {
+            group = group.set("dominant-baseline", baseline);
+        }
+        for (i, label) in column_labels.iter().enumerate() {
+            let text = svg::node::Text::new(label.as_str());
+            group = group.add(
+                element::Text::new()
+                    .set("x", i as f64 + board_margin)
+                    .set("y", y)
+                    .add(text),
+            );
+        }
+        group
+    };
+    let row_group = |x: f64, anchor: &str| {
+        let mut group = element::Group::new()
+            .set("dominant-baseline", "middle")
+            .set("text-anchor", anchor);
+        for (i, label) in row_labels.iter().enumerate() {
+            let text = svg::node::Text::new(label.as_str());
+            group = group.add(
+                element::Text::new()
+                    .set("x", x)
+                    .set("y", i as f64 + board_margin)
+                    .add(text),
+            );
+        }
+        group
+    };
+
+    let transform = format!("translate({}, {})", offset.0, offset.1);
+    let mut labels = element::Group::new()
+        .set("id", "board-labels")
+        .set("font-size", theme.label_font_size)
+        .set("font-family", theme.label_font_family.as_str())
+        .set("font-weight", theme.label_font_weight)
+        .set("fill", theme.label_color.as_str())
+        .set("transform", transform);
+    if top {
+        labels = labels.add(column_group(0.0, ""));
+    }
+    if left {
+        labels = labels.add(row_group(0.0, "end"));
+    }
+    if bottom {
+        labels = labels.add(column_group(board_bottom, "hanging"));
+    }
+    if right {
+        labels = labels.add(row_group(board_right, "start"));
+    }
+
+    labels
+}
+
+/// Draw the caption lines centered below the board, starting at `top`.
+fn draw_caption(lines: &[String], width: f64, top: f64, theme: &Theme) -> element::Group {
+    let mut caption = element::Group::new()
+        .set("id", "caption")
+        .set("font-size", theme.caption_font_size)
+        .set("font-family", theme.label_font_family.as_str())
+        .set("font-weight", theme.label_font_weight)
+        .set("fill", theme.caption_color.as_str())
+        .set("text-anchor", "middle")
+        .set("dominant-baseline", "middle");
+    for (i, line) in lines.iter().enumerate() {
+        caption = caption.add(
             element::Text::new()
-                .set("x", 0.0)
-                .set("y", (end - y - 1) as f64 + BOARD_MARGIN)
-                .add(text),
+                .set("x", width / 2.0)
+                .set("y", top + (i as f64 + 0.5) * theme.caption_line_height)
+                .add(svg::node::Text::new(line.as_str())),
         );
     }
 
-    let transform = format!("translate({}, {})", LABEL_MARGIN, LABEL_MARGIN);
-    element::Group::new()
-        .set("id", "board-labels")
-        .set("font-size", LABEL_FONT_SIZE)
-        .set("font-family", LABEL_FONT_FAMILY)
-        .set("font-weight", LABEL_FONT_WEIGHT)
-        .set("fill", LABEL_COLOR)
-        .set("transform", transform)
-        .add(row_labels)
-        .add(column_labels)
+    caption
+}
+
+/// Formats each footnote like "14 at 8", or "17 at D4" if the point has no numbered stone.
+fn footnote_notes(
+    goban: &Goban,
+    footnotes: &[goban::Footnote],
+    coordinates: &Coordinates,
+) -> Vec<String> {
+    footnotes
+        .iter()
+        .map(|footnote| {
+            let target = match goban.stone_move_numbers.get(&footnote.point) {
+                Some(n) => n.to_string(),
+                None => coordinates.point_label(goban, footnote.point),
+            };
+            format!("{} at {}", footnote.move_number, target)
+        })
+        .collect()
+}
+
+/// Formats footnotes like "14 at 8, 17 at D4", wrapped to fit in `width`.
+fn footnote_lines(
+    goban: &Goban,
+    footnotes: &[goban::Footnote],
+    coordinates: &Coordinates,
+    width: f64,
+    theme: &Theme,
+) -> Vec<String> {
+    let notes = footnote_notes(goban, footnotes, coordinates);
+    let per_line = ((width / theme.footnote_width) as usize).max(1);
+    notes
+        .chunks(per_line)
+        .map(|chunk| chunk.join(", "))
+        .collect()
+}
+
+/// Returns the labels for the columns in `x_range`, and for the rows in `y_range` from the top.
+fn coordinate_labels(
+    goban: &Goban,
+    x_range: &Range<u8>,
+    y_range: &Range<u8>,
+    coordinates: &Coordinates,
+) -> Result<(Vec<String>, Vec<String>), GobanSVGError> {
+    let column_labels = x_range
+        .clone()
+        .map(|x| coordinates.column_label(x))
+        .collect::<Option<_>>();
+    let row_labels = y_range
+        .clone()
+        .map(|y| coordinates.row_label(goban, y))
+        .collect::<Option<_>>();
+    match (column_labels, row_labels) {
+        (Some(column_labels), Some(row_labels)) => Ok((column_labels, row_labels)),
+        _ => Err(GobanSVGError::UnlabellableRange),
+    }
+}
+
+/// Escapes text for use in SVG, since the svg crate writes text nodes as they are.
+pub fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
 }
 
 fn label_text(x: u8) -> String {
@@ -264,17 +1121,67 @@ fn label_text(x: u8) -> String {
     }
 }
 
-#[derive(Debug)]
+fn sgf_letter(n: u8) -> Option<String> {
+    match n {
+        0..=25 => Some(((n + b'a') as char).to_string()),
+        26..=51 => Some(((n - 26 + b'A') as char).to_string()),
+        _ => None,
+    }
+}
+
+fn number_text(n: u8) -> Option<String> {
+    if n < 100 {
+        Some(n.to_string())
+    } else {
+        None
+    }
+}
+
+/// Estimates the width of a label in ems, with CJK characters a full em wide and anything else
+/// a little over half of one.
+fn label_width(label: &str) -> f64 {
+    label
+        .chars()
+        .map(|c| if c >= '\u{2e80}' { 1.0 } else { 0.6 })
+        .sum()
+}
+
+/// Writes 1 to 99 in Japanese or Chinese numerals, e.g. 十九 for 19.
+fn kanji_text(n: u8) -> Option<String> {
+    static DIGITS: [&str; 10] = ["", "一", "二", "三", "四", "五", "六", "七", "八", "九"];
+    let (tens, units) = ((n / 10) as usize, (n % 10) as usize);
+    match tens {
+        0 => Some(DIGITS[units].to_string()),
+        1 => Some(format!("十{}", DIGITS[units])),
+        2..=9 => Some(format!("{}十{}", DIGITS[tens], DIGITS[units])),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum GobanRange {
     ShrinkWrap,
+    /// The whole board, or just the part in the SGF's VW view if there is one.
     FullBoard,
     Ranged(Range<u8>, Range<u8>),
 }
 
 impl GobanRange {
-    fn get_ranges(&self, goban: &Goban) -> Result<(Range<u8>, Range<u8>), GobanSVGError> {
+    pub fn get_ranges(&self, goban: &Goban) -> Result<(Range<u8>, Range<u8>), GobanSVGError> {
         match self {
-            Self::FullBoard => Ok((0..goban.size.0, 0..goban.size.1)),
+            Self::FullBoard => {
+                if goban.view.is_empty() {
+                    return Ok((0..goban.size.0, 0..goban.size.1));
+                }
+                let x_start = goban.view.iter().map(|p| p.0).min().unwrap();
+                let x_end = goban.view.iter().map(|p| p.0 + 1).max().unwrap();
+                let y_start = goban.view.iter().map(|p| p.1).min().unwrap();
+                let y_end = goban.view.iter().map(|p| p.1 + 1).max().unwrap();
+                if x_end > goban.size.0 || y_end > goban.size.1 {
+                    return Err(GobanSVGError::InvalidRange);
+                }
+                Ok((x_start..x_end, y_start..y_end))
+            }
             Self::ShrinkWrap => {
                 let x_start = goban
                     .stones()
@@ -315,6 +1222,8 @@ impl GobanRange {
 pub enum GobanSVGError {
     InvalidRange,
     UnlabellableRange,
+    TooManyMoveNumbers,
+    TooLargeForLatex,
 }
 
 impl std::fmt::Display for GobanSVGError {
@@ -322,8 +1231,32 @@ impl std::fmt::Display for GobanSVGError {
         match self {
             Self::InvalidRange => write!(f, "Invalid range to render in goban."),
             Self::UnlabellableRange => write!(f, "Range too large for use with labels."),
+            Self::TooManyMoveNumbers => write!(f, "Too many numbered moves for a diagram."),
+            Self::TooLargeForLatex => write!(f, "LaTeX diagrams are limited to 25x25 boards."),
         }
     }
 }
 
 impl std::error::Error for GobanSVGError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn point_labels_follow_the_coordinate_scheme() {
+        let goban = Goban::new((19, 19));
+        let label = |scheme: CoordinateScheme| {
+            let coordinates = Coordinates {
+                scheme,
+                rows_from_top: scheme.rows_from_top(),
+                sides: [true, false, false, true],
+            };
+            coordinates.point_label(&goban, (3, 15))
+        };
+        assert_eq!(label(CoordinateScheme::Letters), "D4");
+        assert_eq!(label(CoordinateScheme::Sgf), "dp");
+        assert_eq!(label(CoordinateScheme::Numbers), "4-4");
+        assert_eq!(label(CoordinateScheme::Kanji), "4十六");
+    }
+}
@@ -0,0 +1,541 @@
+//! Sensei's Library wiki diagrams, like:
+//!
+//! ```text
+//! $$Bc9 Black to play
+//! $$ +-------------------+
+//! $$ | . . . . . . . . . |
+//! $$ | . . O O X . . . . |
+//! $$ | . O X X 1 . . . . |
+//! ```
+
+use super::goban::{Goban, Markup, MoveRecord, Stone, StoneColor};
+use super::{footnote_notes, GobanSVGError, MakeSvgOptions};
+use std::collections::HashSet;
+use std::ops::Range;
+
+static DEFAULT_BOARD_SIZE: u8 = 19;
+
+/// Returns whether `text` looks like a Sensei's Library diagram rather than SGF.
+pub fn is_sensei_diagram(text: &str) -> bool {
+    text.trim_start().starts_with("$$")
+}
+
+/// Parses a Sensei's Library diagram into a goban.
+///
+/// Numbered stones are played in order starting from the color in the header, without
+/// captures. A diagram without all four edges is placed against the edges it has, and the goban's
+/// view is limited to the part of the board it shows.
+pub fn parse_sensei(text: &str) -> Result<Goban, DiagramError> {
+    let lines = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| line.strip_prefix("$$").ok_or(DiagramError::InvalidLine))
+        .collect::<Result<Vec<&str>, _>>()?;
+    let (first, rest) = lines.split_first().ok_or(DiagramError::EmptyDiagram)?;
+    // A bare `$$` line with a title is a header unless the title could be a row of the board.
+    let (header, board) = match Header::parse(first) {
+        Some(header) if !(header.is_bare && is_board_row(first, rest)) => (header, rest),
+        _ => (Header::default(), &lines[..]),
+    };
+
+    let (mut top, mut bottom, mut left, mut right) = (false, false, false, false);
+    let mut rows: Vec<Vec<char>> = vec![];
+    for line in board {
+        match board_line(line) {
+            BoardLine::Annotation => {}
+            BoardLine::Edge if rows.is_empty() => top = true,
+            BoardLine::Edge => bottom = true,
+            BoardLine::Row(points, has_left, has_right) => {
+                left |= has_left;
+                right |= has_right;
+                rows.push(points);
+            }
+        }
+    }
+    let height = rows.len();
+    let width = rows.first().map(Vec::len).unwrap_or(0);
+    if width == 0 {
+        return Err(DiagramError::EmptyDiagram);
+    }
+    if rows.iter().any(|row| row.len() != width) {
+        return Err(DiagramError::RaggedRows);
+    }
+
+    let board_size = |size: Option<u8>, length: usize, both_edges: bool| match size {
+        Some(size) => size as usize,
+        None if both_edges => length,
+        None => length.max(DEFAULT_BOARD_SIZE as usize),
+    };
+    let size = (
+        board_size(header.size, width, left && right),
+        board_size(header.size, height, top && bottom),
+    );
+    if width > size.0 || height > size.1 || size.0 > 52 || size.1 > 52 {
+        return Err(DiagramError::TooLarge);
+    }
+    let offset = |length: usize, size: usize, start: bool, end: bool| match (start, end) {
+        (true, _) => 0,
+        (false, true) => size - length,
+        (false, false) => (size - length) / 2,
+    };
+    let x_offset = offset(width, size.0, left, right);
+    let y_offset = offset(height, size.1, top, bottom);
+
+    let mut goban = Goban::new((size.0 as u8, size.1 as u8));
+    goban.title = header.title;
+    let mut numbered = vec![];
+    let mut view = HashSet::new();
+    for (j, row) in rows.iter().enumerate() {
+        for (i, &c) in row.iter().enumerate() {
+            let point = ((x_offset + i) as u8, (y_offset + j) as u8);
+            if c != '_' {
+                view.insert(point);
+            }
+            let (color, markup) = match c {
+                '.' | ',' | '_' => (None, None),
+                'X' => (Some(StoneColor::Black), None),
+                'O' => (Some(StoneColor::White), None),
+                'B' => (Some(StoneColor::Black), Some(Markup::Circle)),
+                'W' => (Some(StoneColor::White), Some(Markup::Circle)),
+                '#' => (Some(StoneColor::Black), Some(Markup::Square)),
+                '@' => (Some(StoneColor::White), Some(Markup::Square)),
+                'Y' => (Some(StoneColor::Black), Some(Markup::Triangle)),
+                'Q' => (Some(StoneColor::White), Some(Markup::Triangle)),
+                'Z' => (Some(StoneColor::Black), Some(Markup::Cross)),
+                'P' => (Some(StoneColor::White), Some(Markup::Cross)),
+                'C' => (None, Some(Markup::Circle)),
+                'S' => (None, Some(Markup::Square)),
+                'T' => (None, Some(Markup::Triangle)),
+                'M' => (None, Some(Markup::Cross)),
+                'a'..='z' => {
+                    goban.labels.insert(point, c.to_string());
+                    (None, None)
+                }
+                '0'..='9' => {
+                    numbered.push((c.to_digit(10).unwrap_or(0), point));
+                    (None, None)
+                }
+                _ => return Err(DiagramError::UnknownSymbol(c)),
+            };
+            if let Some(color) = color {
+                goban.stones.insert(point, color);
+            }
+            if let Some(markup) = markup {
+                goban.marks.insert(point, markup);
+            }
+        }
+    }
+    if view.len() < size.0 * size.1 {
+        goban.view = view;
+    }
+
+    // Moves are numbered 1 to 10, with 0 for 10.
+    numbered.sort_by_key(|&(digit, _)| if digit == 0 { 10 } else { digit });
+    let first_move = header.first_move.unwrap_or(1);
+    for (i, &(_, point)) in numbered.iter().enumerate() {
+        let color = if i % 2 == 0 {
+            header.first_color
+        } else {
+            opponent(header.first_color)
+        };
+        let number = first_move + i as u64;
+        goban.stones.insert(point, color);
+        goban.stone_move_numbers.insert(point, number);
+        goban.moves.push(MoveRecord {
+            number,
+            stone: Stone::new(point.0, point.1, color),
+            captures: vec![],
+            capture_move_numbers: vec![],
+        });
+        goban.move_number = number;
+    }
+
+    Ok(goban)
+}
+
+/// Returns the range of moves numbered in a goban parsed from a diagram, if any.
+pub fn sensei_move_numbers(goban: &Goban) -> Option<Range<u64>> {
+    let first = goban.moves.first()?;
+    Some(first.number..goban.move_number + 1)
+}
+
+/// Returns a Sensei's Library diagram of the goban.
+///
+/// Only stones numbered in `options.move_numbers` are numbered, and the diagram syntax only
+/// allows ten of them. Coordinates are turned on with labels, and edges are drawn where the real
+/// edge of the board is in range. Headers can only give square board sizes, so other boards are
+/// written whole, and their size comes from the edges. Moves that can't be shown on the board
+/// follow the diagram as a line of text like "14 at 8, 17 at D4".
+pub fn make_sensei(goban: &Goban, options: &MakeSvgOptions) -> Result<String, GobanSVGError> {
+    let mut footnotes = vec![];
+    let figure = options.move_numbers.as_ref().map(|moves| {
+        let (mut figure, figure_footnotes) = goban.figure(moves);
+        figure.title = goban.title.clone();
+        footnotes = footnote_notes(&figure, &figure_footnotes, &options.coordinates);
+        figure
+    });
+    let goban = figure.as_ref().unwrap_or(goban);
+    let (x_range, y_range) = if goban.size.0 == goban.size.1 {
+        options.goban_range.get_ranges(goban)?
+    } else {
+        (0..goban.size.0, 0..goban.size.1)
+    };
+    let numbered: Vec<(&(u8, u8), &u64)> = match &options.move_numbers {
+        Some(moves) => goban
+            .stone_move_numbers
+            .iter()
+            .filter(|(point, n)| {
+                moves.contains(n) && x_range.contains(&point.0) && y_range.contains(&point.1)
+            })
+            .collect(),
+        None => vec![],
+    };
+    let first_move = numbered.iter().map(|(_, &n)| n).min();
+    if let Some(first_move) = first_move {
+        if numbered.iter().any(|(_, &n)| n >= first_move + 10) {
+            return Err(GobanSVGError::TooManyMoveNumbers);
+        }
+    }
+
+    let first_color = first_move
+        .and_then(|n| goban.moves.iter().find(|record| record.number == n))
+        .map(|record| record.stone.color)
+        .unwrap_or(StoneColor::Black);
+    let mut header = match first_color {
+        StoneColor::Black => "$$B".to_string(),
+        StoneColor::White => "$$W".to_string(),
+    };
+    if options.render_labels {
+        header.push('c');
+    }
+    if goban.size.0 == goban.size.1 && goban.size.0 != DEFAULT_BOARD_SIZE {
+        header.push_str(&goban.size.0.to_string());
+    }
+    match first_move {
+        Some(n) if n != 1 => header.push_str(&format!("m{}", n)),
+        _ => {}
+    }
+    if let Some(title) = &goban.title {
+        header.push(' ');
+        header.push_str(title);
+    }
+
+    let hoshi: Vec<&(u8, u8)> = goban.hoshi_points().collect();
+    let left = x_range.start == 0;
+    let right = x_range.end == goban.size.0;
+    let width = (x_range.end - x_range.start) as usize;
+    let edge = format!(
+        "$$ {}{}{}",
+        if left { "+-" } else { "" },
+        "-".repeat(2 * width - 1),
+        if right { "-+" } else { "" }
+    );
+    let mut lines = vec![header];
+    if y_range.start == 0 {
+        lines.push(edge.clone());
+    }
+    for y in y_range.clone() {
+        let points: Vec<String> = x_range
+            .clone()
+            .map(|x| {
+                let point = (x, y);
+                if let Some(&(_, &n)) = numbered.iter().find(|(p, _)| **p == point) {
+                    let digit = (n - first_move.unwrap_or(1) + 1) % 10;
+                    return digit.to_string();
+                }
+                let symbol = match (goban.stones.get(&point), goban.marks.get(&point)) {
+                    (Some(StoneColor::Black), Some(Markup::Circle)) => "B",
+                    (Some(StoneColor::White), Some(Markup::Circle)) => "W",
+                    (Some(StoneColor::Black), Some(Markup::Square)) => "#",
+                    (Some(StoneColor::White), Some(Markup::Square)) => "@",
+                    (Some(StoneColor::Black), Some(Markup::Triangle)) => "Y",
+                    (Some(StoneColor::White), Some(Markup::Triangle)) => "Q",
+                    (Some(StoneColor::Black), Some(Markup::Cross)) => "Z",
+                    (Some(StoneColor::White), Some(Markup::Cross)) => "P",
+                    (Some(StoneColor::Black), _) => "X",
+                    (Some(StoneColor::White), _) => "O",
+                    (None, Some(Markup::Circle)) => "C",
+                    (None, Some(Markup::Square)) => "S",
+                    (None, Some(Markup::Triangle)) => "T",
+                    (None, Some(Markup::Cross)) => "M",
+                    (None, _) => match goban.labels.get(&point) {
+                        Some(label) if is_label_symbol(label) => label.as_str(),
+                        _ if hoshi.contains(&&point) => ",",
+                        _ => ".",
+                    },
+                };
+                symbol.to_string()
+            })
+            .collect();
+        lines.push(format!(
+            "$$ {}{}{}",
+            if left { "| " } else { "" },
+            points.join(" "),
+            if right { " |" } else { "" }
+        ));
+    }
+    if y_range.end == goban.size.1 {
+        lines.push(edge);
+    }
+    if !footnotes.is_empty() {
+        lines.push(footnotes.join(", "));
+    }
+
+    Ok(lines.iter().map(|line| format!("{}\n", line)).collect())
+}
+
+/// Returns whether a label can be written as a letter in a diagram.
+fn is_label_symbol(label: &str) -> bool {
+    let mut chars = label.chars();
+    matches!((chars.next(), chars.next()), (Some('a'..='z'), None))
+}
+
+fn opponent(color: StoneColor) -> StoneColor {
+    match color {
+        StoneColor::Black => StoneColor::White,
+        StoneColor::White => StoneColor::Black,
+    }
+}
+
+/// The first line of a diagram, like `$$Wc13m41 Title`.
+struct Header {
+    first_color: StoneColor,
+    size: Option<u8>,
+    first_move: Option<u64>,
+    title: Option<String>,
+    /// Whether the line is just `$$`, maybe with a title.
+    is_bare: bool,
+}
+
+impl Default for Header {
+    fn default() -> Self {
+        Self {
+            first_color: StoneColor::Black,
+            size: None,
+            first_move: None,
+            title: None,
+            is_bare: true,
+        }
+    }
+}
+
+impl Header {
+    /// Parses a header line, without its `$$`, or returns `None` if it can't be one.
+    fn parse(line: &str) -> Option<Self> {
+        let (params, title) = match line.split_once(' ') {
+            Some((params, title)) => (params, title),
+            None => (line, ""),
+        };
+        let mut header = Self::default();
+        let mut rest = params;
+        if let Some(stripped) = rest.strip_prefix('W') {
+            header.first_color = StoneColor::White;
+            rest = stripped;
+        } else if let Some(stripped) = rest.strip_prefix('B') {
+            rest = stripped;
+        }
+        rest = rest.strip_prefix('c').unwrap_or(rest);
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        if digits > 0 {
+            header.size = Some(rest[..digits].parse().ok()?);
+            rest = &rest[digits..];
+        }
+        if let Some(number) = rest.strip_prefix('m') {
+            header.first_move = Some(number.parse().ok()?);
+            rest = "";
+        }
+        if !rest.is_empty() {
+            return None;
+        }
+        let title = title.trim();
+        if !title.is_empty() {
+            header.title = Some(title.to_string());
+        }
+        header.is_bare = params.is_empty();
+
+        Some(header)
+    }
+}
+
+/// A line of the board part of a diagram, without its `$$`.
+enum BoardLine {
+    /// The top or bottom edge of the board.
+    Edge,
+    /// A row of points, with whether it has the left and right edges.
+    Row(Vec<char>, bool, bool),
+    /// Arrow, line and link annotations, which aren't part of the board.
+    Annotation,
+}
+
+fn board_line(line: &str) -> BoardLine {
+    let chars: Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    if chars.is_empty() || chars[0] == '{' || chars[0] == '[' {
+        return BoardLine::Annotation;
+    }
+    if chars.iter().all(|&c| c == '-' || c == '+') {
+        return BoardLine::Edge;
+    }
+    let mut points = &chars[..];
+    let left = points.first() == Some(&'|');
+    if left {
+        points = &points[1..];
+    }
+    let right = points.last() == Some(&'|');
+    if right {
+        points = &points[..points.len() - 1];
+    }
+
+    BoardLine::Row(points.to_vec(), left, right)
+}
+
+/// Returns whether a line could be a row of the board made from the lines after it: an edge, or
+/// as many points as the other rows have, all of them diagram symbols.
+fn is_board_row(line: &str, rest: &[&str]) -> bool {
+    match board_line(line) {
+        BoardLine::Edge => true,
+        BoardLine::Row(points, _, _) => {
+            let width = rest.iter().find_map(|line| match board_line(line) {
+                BoardLine::Row(points, _, _) => Some(points.len()),
+                _ => None,
+            });
+            width.unwrap_or(points.len()) == points.len()
+                && points.iter().all(|&c| {
+                    c.is_ascii_lowercase() || c.is_ascii_digit() || ".,_XOBW#@YQZPCSTM".contains(c)
+                })
+        }
+        BoardLine::Annotation => false,
+    }
+}
+
+#[derive(Debug)]
+pub enum DiagramError {
+    EmptyDiagram,
+    InvalidLine,
+    RaggedRows,
+    TooLarge,
+    UnknownSymbol(char),
+}
+
+impl std::fmt::Display for DiagramError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EmptyDiagram => write!(f, "No board found in diagram."),
+            Self::InvalidLine => write!(f, "Diagram lines must start with '$$'."),
+            Self::RaggedRows => write!(f, "Diagram rows differ in length."),
+            Self::TooLarge => write!(f, "Diagram too large for board size."),
+            Self::UnknownSymbol(c) => write!(f, "Unknown diagram symbol '{}'.", c),
+        }
+    }
+}
+
+impl std::error::Error for DiagramError {}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Coordinates, CroppedEdges, GobanRange, Theme};
+    use super::*;
+
+    fn options(goban: &Goban, goban_range: GobanRange) -> MakeSvgOptions {
+        MakeSvgOptions {
+            goban_range,
+            viewbox_width: 800.0,
+            render_labels: true,
+            coordinates: Coordinates::default(),
+            render_score: false,
+            move_numbers: sensei_move_numbers(goban),
+            animation: None,
+            cropped_edges: CroppedEdges::Lines,
+            theme: Theme::default(),
+        }
+    }
+
+    fn round_trip(diagram: &str) -> String {
+        let goban = parse_sensei(diagram).unwrap();
+        make_sensei(&goban, &options(&goban, GobanRange::FullBoard)).unwrap()
+    }
+
+    #[test]
+    fn round_trips_a_numbered_corner() {
+        let diagram = "$$Wc13m41 Black to play\n\
+                       $$ +------------\n\
+                       $$ | . . . . . .\n\
+                       $$ | . . X 1 . .\n\
+                       $$ | . O O 2 . .\n\
+                       $$ | . . . , a .\n";
+        assert_eq!(round_trip(diagram), diagram);
+
+        let goban = parse_sensei(diagram).unwrap();
+        assert_eq!(goban.size, (13, 13));
+        assert_eq!(goban.title.as_deref(), Some("Black to play"));
+        assert_eq!(goban.stone_move_numbers.get(&(3, 1)), Some(&41));
+        assert_eq!(goban.stones.get(&(3, 1)), Some(&StoneColor::White));
+        assert_eq!(goban.stones.get(&(3, 2)), Some(&StoneColor::Black));
+        assert_eq!(goban.labels.get(&(4, 3)).map(String::as_str), Some("a"));
+    }
+
+    #[test]
+    fn round_trips_marks_on_a_whole_board() {
+        let diagram = "$$Bc5\n\
+                       $$ +-----------+\n\
+                       $$ | B W # @ . |\n\
+                       $$ | Y Q Z P . |\n\
+                       $$ | C S T M . |\n\
+                       $$ | . . . . . |\n\
+                       $$ | . . . . . |\n\
+                       $$ +-----------+\n";
+        assert_eq!(round_trip(diagram), diagram);
+    }
+
+    #[test]
+    fn lowercase_titles_are_not_board_rows() {
+        let diagram = "$$ black to play\n\
+                       $$ +-------+\n\
+                       $$ | . X . |\n\
+                       $$ | . . . |\n\
+                       $$ +-------+\n";
+        let goban = parse_sensei(diagram).unwrap();
+        assert_eq!(goban.size, (3, 2));
+        assert_eq!(goban.title.as_deref(), Some("black to play"));
+        assert_eq!(goban.stones.get(&(1, 0)), Some(&StoneColor::Black));
+    }
+
+    #[test]
+    fn bare_first_lines_can_be_board_rows() {
+        let diagram = "$$ | a b c |\n$$ | . X . |\n$$ +-------+\n";
+        let goban = parse_sensei(diagram).unwrap();
+        assert_eq!(goban.title, None);
+        assert_eq!(goban.labels.get(&(0, 17)).map(String::as_str), Some("a"));
+        assert_eq!(goban.stones.get(&(1, 18)), Some(&StoneColor::Black));
+    }
+
+    #[test]
+    fn non_square_boards_keep_their_size() {
+        let diagram = "$$ +---------+\n\
+                       $$ | . . X . . |\n\
+                       $$ | . . . . . |\n\
+                       $$ | . . . . . |\n\
+                       $$ +---------+\n";
+        let goban = parse_sensei(diagram).unwrap();
+        assert_eq!(goban.size, (5, 3));
+        let corner = GobanRange::Ranged(0..2, 0..2);
+        let written = make_sensei(&goban, &options(&goban, corner)).unwrap();
+        assert_eq!(parse_sensei(&written).unwrap().size, (5, 3));
+    }
+
+    #[test]
+    fn moves_on_occupied_points_follow_as_text() {
+        let mut goban = Goban::new((5, 5));
+        for &(x, y, color) in &[
+            (0, 0, StoneColor::Black),
+            (1, 0, StoneColor::White),
+            (4, 4, StoneColor::Black),
+            (0, 1, StoneColor::White),
+            (0, 0, StoneColor::Black),
+        ] {
+            goban.play_stone(Stone::new(x, y, color)).unwrap();
+        }
+        let written = make_sensei(&goban, &options(&goban, GobanRange::FullBoard)).unwrap();
+        assert_eq!(written.lines().last(), Some("5 at 1"));
+    }
+}
@@ -0,0 +1,221 @@
+use unicode_width::UnicodeWidthStr;
+
+use super::goban::{Goban, StoneColor};
+use super::{coordinate_labels, GobanSVGError, MakeSvgOptions};
+
+static ANSI_BOARD: &str = "\x1b[48;5;179m\x1b[38;5;16m";
+static ANSI_WHITE_STONE: &str = "\x1b[97m";
+static ANSI_BLACK_STONE: &str = "\x1b[38;5;16m";
+static ANSI_RESET: &str = "\x1b[0m";
+
+/// Returns a plain text diagram of the goban.
+///
+/// Black stones are `X`, white stones `O`, hoshi `+`, and other empty points `.`, with
+/// coordinates along the top and left if labels are on.
+pub fn make_ascii(goban: &Goban, options: &MakeSvgOptions) -> Result<String, GobanSVGError> {
+    let hoshi: Vec<&(u8, u8)> = goban.hoshi_points().collect();
+    let lines = layout(goban, options, " ", |x, y| {
+        match goban.stones.get(&(x, y)) {
+            Some(StoneColor::Black) => "X",
+            Some(StoneColor::White) => "O",
+            None if hoshi.contains(&&(x, y)) => "+",
+            None => ".",
+        }
+        .to_string()
+    })?;
+
+    Ok(lines.iter().map(|line| format!("{}\n", line)).collect())
+}
+
+/// Returns a diagram of the goban drawn with box-drawing characters, for terminals.
+///
+/// Black stones are `●` and white stones `○`. Corners and sides are only drawn where the real
+/// edge of the board is in range. With `color` set, the board gets a wood background using ANSI
+/// escapes, and both colors of stone are drawn as solid discs.
+pub fn make_unicode(
+    goban: &Goban,
+    options: &MakeSvgOptions,
+    color: bool,
+) -> Result<String, GobanSVGError> {
+    let hoshi: Vec<&(u8, u8)> = goban.hoshi_points().collect();
+    let (last_x, last_y) = (goban.size.0 - 1, goban.size.1 - 1);
+    let lines = layout(goban, options, "─", |x, y| {
+        let glyph = match (goban.stones.get(&(x, y)), color) {
+            (Some(StoneColor::Black), false) => "●",
+            (Some(StoneColor::White), false) => "○",
+            (Some(StoneColor::Black), true) => {
+                return format!("{}●{}", ANSI_BLACK_STONE, ANSI_BOARD);
+            }
+            (Some(StoneColor::White), true) => {
+                return format!("{}●{}", ANSI_WHITE_STONE, ANSI_BOARD);
+            }
+            (None, _) if hoshi.contains(&&(x, y)) => "╋",
+            (None, _) => match (x == 0, x == last_x, y == 0, y == last_y) {
+                (true, _, true, _) => "┌",
+                (_, true, true, _) => "┐",
+                (true, _, _, true) => "└",
+                (_, true, _, true) => "┘",
+                (_, _, true, _) => "┬",
+                (_, _, _, true) => "┴",
+                (true, _, _, _) => "├",
+                (_, true, _, _) => "┤",
+                _ => "┼",
+            },
+        };
+        glyph.to_string()
+    })?;
+
+    Ok(lines
+        .iter()
+        .map(|line| {
+            if color {
+                format!("{} {} {}\n", ANSI_BOARD, line, ANSI_RESET)
+            } else {
+                format!("{}\n", line)
+            }
+        })
+        .collect())
+}
+
+/// Lays out the points in range as lines of text, with `separator` between the points in each
+/// row, and coordinates on the sides set in `options.coordinates` if labels are on.
+fn layout(
+    goban: &Goban,
+    options: &MakeSvgOptions,
+    separator: &str,
+    point: impl Fn(u8, u8) -> String,
+) -> Result<Vec<String>, GobanSVGError> {
+    let (x_range, y_range) = options.goban_range.get_ranges(goban)?;
+    let labels = if options.render_labels {
+        Some(coordinate_labels(
+            goban,
+            &x_range,
+            &y_range,
+            &options.coordinates,
+        )?)
+    } else {
+        None
+    };
+    let [top, right, bottom, left] = match labels {
+        Some(_) => options.coordinates.sides,
+        None => [false; 4],
+    };
+    // Row labels are right aligned by display width, so that wide characters like kanji keep
+    // the board lined up.
+    let row_label_width = match &labels {
+        Some((_, row_labels)) => row_labels.iter().map(|label| label.width()).max(),
+        None => None,
+    }
+    .unwrap_or(0);
+    let indent = if left {
+        " ".repeat(row_label_width + 1)
+    } else {
+        String::new()
+    };
+    let column_lines: Vec<String> = match &labels {
+        Some((column_labels, _)) => column_label_lines(column_labels)
+            .iter()
+            .map(|line| format!("{}{}", indent, line).trim_end().to_string())
+            .collect(),
+        None => vec![],
+    };
+    let mut lines = vec![];
+    if top {
+        lines.extend(column_lines.iter().cloned());
+    }
+    for (i, y) in y_range.enumerate() {
+        let points: Vec<String> = x_range.clone().map(|x| point(x, y)).collect();
+        let mut line = points.join(separator);
+        if let Some((_, row_labels)) = &labels {
+            let label = &row_labels[i];
+            if left {
+                let padding = " ".repeat(row_label_width - label.width());
+                line = format!("{}{} {}", padding, label, line);
+            }
+            if right {
+                line = format!("{} {}", line, label);
+            }
+        }
+        lines.push(line);
+    }
+    if bottom {
+        lines.extend(column_lines);
+    }
+
+    Ok(lines)
+}
+
+/// Writes column labels a character to a point, with longer labels stacked over several lines,
+/// e.g. the tens of two digit numbers above their units.
+fn column_label_lines(labels: &[String]) -> Vec<String> {
+    let labels: Vec<Vec<char>> = labels.iter().map(|label| label.chars().collect()).collect();
+    let height = labels.iter().map(Vec::len).max().unwrap_or(0);
+    (0..height)
+        .map(|row| {
+            labels
+                .iter()
+                .map(|label| match (row + label.len()).checked_sub(height) {
+                    Some(i) => label[i].to_string(),
+                    None => " ".to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{CoordinateScheme, Coordinates, CroppedEdges, GobanRange, Theme};
+    use super::*;
+
+    fn options(scheme: CoordinateScheme, sides: [bool; 4]) -> MakeSvgOptions {
+        MakeSvgOptions {
+            goban_range: GobanRange::Ranged(7..12, 0..12),
+            viewbox_width: 800.0,
+            render_labels: true,
+            coordinates: Coordinates {
+                scheme,
+                rows_from_top: true,
+                sides,
+            },
+            render_score: false,
+            move_numbers: None,
+            animation: None,
+            cropped_edges: CroppedEdges::Lines,
+            theme: Theme::default(),
+        }
+    }
+
+    #[test]
+    fn two_digit_columns_stack_over_their_points() {
+        let goban = Goban::new((19, 19));
+        let options = options(CoordinateScheme::Numbers, [true, false, true, false]);
+        let diagram = make_ascii(&goban, &options).unwrap();
+        let lines: Vec<&str> = diagram.lines().collect();
+        assert_eq!(lines[0], "    1 1 1");
+        assert_eq!(lines[1], "8 9 0 1 2");
+        assert_eq!(lines[2], ". . . . .");
+        assert_eq!(lines[lines.len() - 2], "    1 1 1");
+        assert_eq!(lines[lines.len() - 1], "8 9 0 1 2");
+    }
+
+    #[test]
+    fn kanji_rows_pad_to_their_display_width() {
+        let goban = Goban::new((19, 19));
+        let options = options(CoordinateScheme::Kanji, [true, true, false, true]);
+        let diagram = make_unicode(&goban, &options, false).unwrap();
+        let lines: Vec<&str> = diagram.lines().collect();
+        assert_eq!(lines[0], "         1 1 1");
+        assert_eq!(lines[1], "     8 9 0 1 2");
+        assert_eq!(lines[2], "  一 ┬─┬─┬─┬─┬ 一");
+        assert_eq!(lines[10], "  九 ┼─┼─┼─┼─┼ 九");
+        assert_eq!(lines[11], "  十 ┼─┼─╋─┼─┼ 十");
+        assert_eq!(lines[12], "十一 ┼─┼─┼─┼─┼ 十一");
+        // The right labels end the lines, so everything before them should line up.
+        let board_width = |line: &str| line.rsplit_once(' ').unwrap().0.width();
+        for line in &lines[2..] {
+            assert_eq!(board_width(line), board_width(lines[2]));
+        }
+    }
+}
@@ -0,0 +1,280 @@
+//! Styling for SVG diagrams, loadable from TOML or JSON.
+//!
+//! A theme file sets any of the `Theme` fields by name, and the rest keep the values of the style
+//! it was loaded over:
+//!
+//! ```toml
+//! board_color = "#e0b878"
+//! line_width = 0.05
+//! black_stone_gradient = [[0, "#555"], [100, "black"]]
+//! ```
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Colors, fonts and sizes for drawing diagrams.
+///
+/// Sizes are in units of the space between lines on the board.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub board_color: String,
+    pub board_margin: f64,
+
+    pub label_color: String,
+    pub label_font_size: f64,
+    pub label_font_family: String,
+    pub label_font_weight: usize,
+    pub label_margin: f64,
+
+    pub line_color: String,
+    /// Width of the lines inside the board.
+    pub line_width: f64,
+    /// Width of the lines along the edges of the board.
+    pub edge_line_width: f64,
+    pub hoshi_radius: f64,
+
+    pub stone_radius: f64,
+    /// Stones have no shadows with an opacity of 0.
+    pub stone_shadow_opacity: f64,
+    /// Stones have no outlines with a width of 0.
+    pub stone_outline_width: f64,
+    pub stone_outline_color: String,
+    /// Radial gradient stops for black stones as (percent offset, color) pairs.
+    #[serde(deserialize_with = "gradient")]
+    pub black_stone_gradient: Vec<(f64, String)>,
+    /// Radial gradient stops for white stones as (percent offset, color) pairs.
+    #[serde(deserialize_with = "gradient")]
+    pub white_stone_gradient: Vec<(f64, String)>,
+
+    pub markup_width: f64,
+    pub markup_color_on_black: String,
+    pub markup_color_on_white: String,
+    pub markup_color_on_board: String,
+    pub selected_opacity: f64,
+    pub label_mask_radius: f64,
+    pub arrow_color: String,
+    pub arrowhead_size: f64,
+    pub dimmed_opacity: f64,
+    pub territory_size: f64,
+
+    pub caption_color: String,
+    pub caption_font_size: f64,
+    pub caption_line_height: f64,
+    pub footnote_width: f64,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            board_color: "#cfa87e".to_string(),
+            board_margin: 0.64,
+
+            label_color: "#6e5840".to_string(),
+            label_font_size: 0.5,
+            label_font_family: "Roboto".to_string(),
+            label_font_weight: 700,
+            label_margin: 0.8,
+
+            line_color: "black".to_string(),
+            line_width: 0.045,
+            edge_line_width: 0.045,
+            hoshi_radius: 0.09,
+
+            stone_radius: 0.475,
+            stone_shadow_opacity: 0.5,
+            stone_outline_width: 0.0,
+            stone_outline_color: "black".to_string(),
+            black_stone_gradient: vec![(0.0, "#666".to_string()), (100.0, "black".to_string())],
+            white_stone_gradient: vec![
+                (0.0, "#ddd".to_string()),
+                (30.0, "#bbb".to_string()),
+                (100.0, "#9a9a9a".to_string()),
+            ],
+
+            markup_width: 0.1,
+            markup_color_on_black: "white".to_string(),
+            markup_color_on_white: "black".to_string(),
+            markup_color_on_board: "black".to_string(),
+            selected_opacity: 0.5,
+            label_mask_radius: 0.4,
+            arrow_color: "black".to_string(),
+            arrowhead_size: 4.0,
+            dimmed_opacity: 0.6,
+            territory_size: 0.3,
+
+            caption_color: "black".to_string(),
+            caption_font_size: 0.5,
+            caption_line_height: 0.75,
+            footnote_width: 2.5,
+        }
+    }
+}
+
+impl Theme {
+    /// A monochrome theme for printing, with a white board, flat stones and no shadows.
+    pub fn print() -> Self {
+        Self {
+            board_color: "white".to_string(),
+            label_color: "black".to_string(),
+            line_width: 0.05,
+            edge_line_width: 0.12,
+            hoshi_radius: 0.1,
+            stone_radius: 0.47,
+            stone_shadow_opacity: 0.0,
+            stone_outline_width: 0.06,
+            black_stone_gradient: vec![(0.0, "black".to_string())],
+            white_stone_gradient: vec![(0.0, "white".to_string())],
+            ..Self::default()
+        }
+    }
+
+    /// Sets the fields named in a theme file, which may be either a flat TOML table or a flat
+    /// JSON object.
+    ///
+    /// Colors and font families are strings, gradients are arrays of `[offset, color]` pairs or a
+    /// single color for a flat fill, and everything else is a number.
+    pub fn load(&mut self, text: &str) -> Result<(), ThemeError> {
+        // Parsing to a `Theme` checks the values, and parsing to a table finds which were set.
+        let (theme, keys): (Self, Vec<String>) = if text.trim_start().starts_with('{') {
+            let table: serde_json::Map<String, serde_json::Value> =
+                serde_json::from_str(text).map_err(ThemeError::Json)?;
+            let theme = serde_json::from_str(text).map_err(ThemeError::Json)?;
+            (theme, table.keys().cloned().collect())
+        } else {
+            let table: toml::value::Table = toml::from_str(text).map_err(ThemeError::Toml)?;
+            let theme = toml::from_str(text).map_err(ThemeError::Toml)?;
+            (theme, table.keys().cloned().collect())
+        };
+        for (key, gradient) in &[
+            ("black_stone_gradient", &theme.black_stone_gradient),
+            ("white_stone_gradient", &theme.white_stone_gradient),
+        ] {
+            if gradient.is_empty() {
+                return Err(ThemeError::EmptyGradient(key.to_string()));
+            }
+        }
+
+        let mut merged = serde_json::to_value(&*self).map_err(ThemeError::Json)?;
+        let overrides = serde_json::to_value(&theme).map_err(ThemeError::Json)?;
+        for key in keys {
+            merged[&key] = overrides[&key].clone();
+        }
+        *self = serde_json::from_value(merged).map_err(ThemeError::Json)?;
+
+        Ok(())
+    }
+}
+
+/// Reads a gradient given either as `[offset, color]` pairs or as a single color.
+fn gradient<'de, D>(deserializer: D) -> Result<Vec<(f64, String)>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Gradient {
+        Flat(String),
+        Stops(Vec<(f64, String)>),
+    }
+
+    Ok(match Gradient::deserialize(deserializer)? {
+        Gradient::Flat(color) => vec![(0.0, color)],
+        Gradient::Stops(stops) => stops,
+    })
+}
+
+#[derive(Debug)]
+pub enum ThemeError {
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    EmptyGradient(String),
+}
+
+impl std::fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Toml(e) => write!(f, "{}", e),
+            Self::Json(e) => write!(f, "{}", e),
+            Self::EmptyGradient(key) => write!(f, "Empty gradient for theme key '{}'.", key),
+        }
+    }
+}
+
+impl std::error::Error for ThemeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loads_toml_over_the_current_theme() {
+        let mut theme = Theme::print();
+        theme
+            .load(
+                "# Comments are allowed.\nline_width = 1e-2\nlabel_font_weight = 400\n\
+                 black_stone_gradient = [[0, \"#555\"], [100.0, \"black\"]]\n\
+                 white_stone_gradient = \"#eee\"\n",
+            )
+            .unwrap();
+        assert_eq!(theme.line_width, 0.01);
+        assert_eq!(theme.label_font_weight, 400);
+        assert_eq!(
+            theme.black_stone_gradient,
+            vec![(0.0, "#555".to_string()), (100.0, "black".to_string())]
+        );
+        assert_eq!(theme.white_stone_gradient, vec![(0.0, "#eee".to_string())]);
+        assert_eq!(theme.board_color, "white");
+        assert_eq!(theme.edge_line_width, Theme::print().edge_line_width);
+    }
+
+    #[test]
+    fn loads_json() {
+        let mut theme = Theme::default();
+        theme
+            .load("{\"board_color\": \"#e0b878\", \"hoshi_radius\": 1.5E-1}")
+            .unwrap();
+        assert_eq!(theme.board_color, "#e0b878");
+        assert_eq!(theme.hoshi_radius, 0.15);
+        assert_eq!(theme.line_width, Theme::default().line_width);
+    }
+
+    #[test]
+    fn rejects_json_without_commas() {
+        let error = Theme::default()
+            .load("{\"board_color\": \"white\" \"line_width\": 0.1}")
+            .unwrap_err();
+        assert!(matches!(error, ThemeError::Json(_)));
+    }
+
+    #[test]
+    fn reports_the_line_of_syntax_errors() {
+        let error = Theme::default()
+            .load("board_color = \"white\"\n\nline_width = = 0.1\n")
+            .unwrap_err();
+        assert!(error.to_string().contains("line 3"), "{}", error);
+        let error = Theme::default()
+            .load("{\n  \"board_color\": \"white\",\n  \"line_width\": 0.1,,\n}")
+            .unwrap_err();
+        assert!(error.to_string().contains("line 3"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_wrong_types() {
+        assert!(Theme::default().load("board_colour = \"white\"").is_err());
+        assert!(Theme::default().load("line_width = \"thin\"").is_err());
+        assert!(Theme::default()
+            .load("black_stone_gradient = [[\"black\", 0]]")
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_empty_gradients() {
+        let error = Theme::default()
+            .load("white_stone_gradient = []")
+            .unwrap_err();
+        assert!(matches!(error, ThemeError::EmptyGradient(key) if key == "white_stone_gradient"));
+        assert!(Theme::default()
+            .load("{\"black_stone_gradient\": []}")
+            .is_err());
+    }
+}
@@ -1,12 +1,24 @@
 #![feature(str_split_once)]
 
+#[cfg(feature = "png")]
+mod animation;
 mod args;
+mod batch;
+#[cfg(feature = "pdf")]
+mod book;
 mod lib;
+mod output;
+#[cfg(feature = "pdf")]
+mod pdf;
 
+#[cfg(feature = "pdf")]
+use book::render_book;
 use lib::Goban;
+use output::OutputWriter;
+use sgf_parse::SgfProp;
 use std::error::Error;
-use std::path::PathBuf;
-use svg::node::element::SVG;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
@@ -24,92 +36,425 @@ fn main() {
         return;
     }
 
-    let goban = match load_goban(&parsed_args.infile, parsed_args.move_number) {
-        Ok(goban) => goban,
-        Err(e) => {
-            eprintln!("Failed to load SGF node: {}", e);
+    if parsed_args.list_games {
+        if let Err(e) = list_games(&parsed_args.infile) {
+            eprintln!("Failed to list games: {}", e);
             std::process::exit(1);
         }
-    };
+        return;
+    }
+
+    if let Some(outdir) = &parsed_args.outdir {
+        if let Err(e) = batch::render_batch(&parsed_args, outdir) {
+            eprintln!("Failed to render batch: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    let document = match lib::make_svg(&goban, &parsed_args.options) {
-        Ok(document) => document,
+    if parsed_args.book {
+        if let Err(e) = render_book(&parsed_args) {
+            eprintln!("Failed to render book: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if parsed_args.animate {
+        if let Err(e) = render_animation(&parsed_args) {
+            eprintln!("Failed to render animation: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if parsed_args.all_moves {
+        if let Err(e) = render_all_moves(&parsed_args) {
+            eprintln!("Failed to render frames: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if parsed_args.figures {
+        if let Err(e) = render_figures(&parsed_args) {
+            eprintln!("Failed to render figures: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let (goban, options) = match load_goban(&parsed_args) {
+        Ok(loaded) => loaded,
         Err(e) => {
-            eprintln!("Failed to generate SVG: {}", e);
+            eprintln!("Failed to load input: {}", e);
             std::process::exit(1);
         }
     };
 
-    let result: Result<(), Box<dyn Error>> = match parsed_args.outfile {
-        Some(filename) => write_to_file(&filename, &document).into(),
-        None => svg::write(std::io::stdout(), &document).map_err(|e| e.into()),
-    };
+    let result = OutputWriter::new().write_diagram(
+        parsed_args.outfile.as_deref(),
+        &goban,
+        &options,
+        parsed_args.format,
+    );
     if let Err(e) = result {
         eprintln!("Failed to write output: {}", e);
         std::process::exit(1);
     }
 }
 
-fn load_goban(infile: &Option<PathBuf>, move_number: u64) -> Result<Goban, Box<dyn Error>> {
-    let mut sgf_node = &get_sgf_root(infile)?;
+/// Returns the goban to render from an SGF file or a Sensei's Library diagram.
+///
+/// Diagrams number their moves, so the options returned number them too unless the move numbers
+/// were set explicitly.
+fn load_goban(
+    parsed_args: &args::SgfRenderArgs,
+) -> Result<(Goban, lib::MakeSvgOptions), Box<dyn Error>> {
+    let text = read_input(&parsed_args.infile)?;
+    let mut options = parsed_args.options.clone();
+    if lib::is_sensei_diagram(&text) {
+        let goban = lib::parse_sensei(&text)?;
+        if options.move_numbers.is_none() {
+            options.move_numbers = lib::sensei_move_numbers(&goban);
+        }
+        return Ok((goban, options));
+    }
+    let sgf_root = select_game(sgf_parse::parse(&text)?, parsed_args.game)?;
+    let goban = goban_at_node(&sgf_root, parsed_args.move_number, &parsed_args.node_path)?;
+
+    Ok((goban, options))
+}
+
+/// Returns the goban at node `move_number` along `node_path`, starting from `sgf_root`.
+fn goban_at_node(
+    sgf_root: &sgf_parse::SgfNode,
+    move_number: u64,
+    node_path: &args::NodePath,
+) -> Result<Goban, Box<dyn Error>> {
+    let (_, goban) = find_node(sgf_root, move_number, node_path)?;
+
+    Ok(goban)
+}
 
-    let mut goban = Goban::from_sgf_node(&sgf_node)?;
-    for _ in 1..move_number {
-        sgf_node = sgf_node
-            .children()
-            .next()
+/// Returns node `move_number` along `node_path`, and the goban at that node.
+fn find_node<'a>(
+    sgf_root: &'a sgf_parse::SgfNode,
+    move_number: u64,
+    node_path: &args::NodePath,
+) -> Result<(&'a sgf_parse::SgfNode, Goban), Box<dyn Error>> {
+    let mut sgf_node = sgf_root;
+    let mut goban = Goban::from_sgf_node(sgf_node)?;
+    let mut branch_point = 0;
+    for node_number in 1..move_number {
+        sgf_node = next_node(sgf_node, node_number, node_path, &mut branch_point)?
             .ok_or(SgfRenderError::InsufficientSgfNodes)?;
         goban.process_node(sgf_node)?;
     }
 
-    Ok(goban)
+    Ok((sgf_node, goban))
+}
+
+/// Returns the child of `sgf_node` to follow along `node_path`, or `None` at the end of the game.
+///
+/// `branch_point` counts the nodes with more than one child passed so far.
+fn next_node<'a>(
+    sgf_node: &'a sgf_parse::SgfNode,
+    node_number: u64,
+    node_path: &args::NodePath,
+    branch_point: &mut usize,
+) -> Result<Option<&'a sgf_parse::SgfNode>, SgfRenderError> {
+    let children: Vec<_> = sgf_node.children().collect();
+    let branch = if children.len() > 1 {
+        *branch_point += 1;
+        node_path.branch(node_number, Some(*branch_point - 1))
+    } else {
+        node_path.branch(node_number, None)
+    };
+    match children.get(branch) {
+        Some(child) => Ok(Some(child)),
+        None if children.is_empty() => Ok(None),
+        None => Err(SgfRenderError::MissingVariation(format!(
+            "{} (node {} has no branch {})",
+            node_path, node_number, branch
+        ))),
+    }
+}
+
+/// Writes a frame for every node along `node_path`, stepping a single goban through the game.
+fn render_all_moves(parsed_args: &args::SgfRenderArgs) -> Result<(), Box<dyn Error>> {
+    let outfile = parsed_args
+        .outfile
+        .as_ref()
+        .ok_or(SgfRenderError::NoOutfile)?;
+    let sgf_root = get_sgf_root(&parsed_args.infile, parsed_args.game)?;
+    let writer = OutputWriter::new();
+    let mut sgf_node = &sgf_root;
+    let mut goban = Goban::from_sgf_node(sgf_node)?;
+    let mut branch_point = 0;
+    let mut node_number = 1;
+    loop {
+        writer.write_diagram(
+            Some(&numbered_filename(outfile, node_number, 3)),
+            &goban,
+            &parsed_args.options,
+            parsed_args.format,
+        )?;
+        sgf_node = match next_node(
+            sgf_node,
+            node_number,
+            &parsed_args.node_path,
+            &mut branch_point,
+        )? {
+            Some(node) => node,
+            None => break,
+        };
+        goban.process_node(sgf_node)?;
+        node_number += 1;
+    }
+
+    Ok(())
+}
+
+/// Walks the game along `node_path` writing a numbered figure for every `figure_moves` moves, and
+/// at every node with an FG property.
+fn render_figures(parsed_args: &args::SgfRenderArgs) -> Result<(), Box<dyn Error>> {
+    let outfile = parsed_args
+        .outfile
+        .as_ref()
+        .ok_or(SgfRenderError::NoOutfile)?;
+    let sgf_root = get_sgf_root(&parsed_args.infile, parsed_args.game)?;
+    let mut sgf_node = &sgf_root;
+    let mut goban = Goban::from_sgf_node(sgf_node)?;
+    let writer = OutputWriter::new();
+    let mut figure_start = goban.moves.len();
+    let mut figure_number = 1;
+    let mut branch_point = 0;
+    let mut node_number = 1;
+    loop {
+        let next_node = next_node(
+            sgf_node,
+            node_number,
+            &parsed_args.node_path,
+            &mut branch_point,
+        )?;
+        let figure_full = match parsed_args.figure_moves {
+            Some(n) => (goban.moves.len() - figure_start) as u64 >= n,
+            None => false,
+        };
+        let at_figure_break = figure_full
+            || match next_node {
+                None => true,
+                Some(node) => node.get_property("FG").is_some(),
+            };
+        if at_figure_break && goban.moves.len() > figure_start {
+            let first_move = goban.moves[figure_start].number;
+            let options = lib::MakeSvgOptions {
+                move_numbers: Some(first_move..goban.move_number + 1),
+                ..parsed_args.options.clone()
+            };
+            writer.write_diagram(
+                Some(&numbered_filename(outfile, figure_number, 2)),
+                &goban,
+                &options,
+                parsed_args.format,
+            )?;
+            figure_number += 1;
+            figure_start = goban.moves.len();
+        }
+        sgf_node = match next_node {
+            Some(node) => node,
+            None => break,
+        };
+        goban.process_node(sgf_node)?;
+        node_number += 1;
+    }
+
+    Ok(())
 }
 
-fn get_sgf_root(infile: &Option<PathBuf>) -> Result<sgf_parse::SgfNode, Box<dyn Error>> {
+/// Writes an animation of every node along `node_path` from node `move_number` on.
+///
+/// SVG animations fade the moves in one after another, and other formats get a frame per node.
+/// Shrink wrapped ranges are fit to all the frames so the frames all have the same size.
+fn render_animation(parsed_args: &args::SgfRenderArgs) -> Result<(), Box<dyn Error>> {
+    let outfile = parsed_args
+        .outfile
+        .as_ref()
+        .ok_or(SgfRenderError::NoOutfile)?;
+    let sgf_root = get_sgf_root(&parsed_args.infile, parsed_args.game)?;
+    let mut sgf_node = &sgf_root;
+    let mut goban = Goban::from_sgf_node(sgf_node)?;
+    let mut branch_point = 0;
+    let mut node_number = 1;
+    let mut frames = vec![];
+    loop {
+        if node_number >= parsed_args.move_number {
+            let delay = match parsed_args.comment_delay {
+                Some(delay) if sgf_node.get_property("C").is_some() => delay,
+                _ => parsed_args.frame_delay,
+            };
+            frames.push((goban.clone(), delay));
+        }
+        sgf_node = match next_node(
+            sgf_node,
+            node_number,
+            &parsed_args.node_path,
+            &mut branch_point,
+        )? {
+            Some(node) => node,
+            None => break,
+        };
+        goban.process_node(sgf_node)?;
+        node_number += 1;
+    }
+    if frames.is_empty() {
+        Err(SgfRenderError::InsufficientSgfNodes)?;
+    }
+
+    let mut options = parsed_args.options.clone();
+    if let lib::GobanRange::ShrinkWrap = options.goban_range {
+        let mut ranges: Option<(Range<u8>, Range<u8>)> = None;
+        for (goban, _) in frames.iter() {
+            let (x, y) = options.goban_range.get_ranges(goban)?;
+            ranges = Some(match ranges {
+                Some((x_range, y_range)) => (
+                    x_range.start.min(x.start)..x_range.end.max(x.end),
+                    y_range.start.min(y.start)..y_range.end.max(y.end),
+                ),
+                None => (x, y),
+            });
+        }
+        if let Some((x_range, y_range)) = ranges {
+            options.goban_range = lib::GobanRange::Ranged(x_range, y_range);
+        }
+    }
+    if outfile.extension().and_then(std::ffi::OsStr::to_str) == Some("svg") {
+        // Each frame's moves appear once the frames before it have had their delays.
+        let mut move_times = vec![];
+        let mut end = 0;
+        for pair in frames.windows(2) {
+            let ((previous, delay), (next, _)) = (&pair[0], &pair[1]);
+            end += *delay as u64;
+            for _ in previous.move_number..next.move_number {
+                move_times.push(end);
+            }
+        }
+        let (first, _) = &frames[0];
+        let (last, _) = &frames[frames.len() - 1];
+        options.animation = Some(lib::SvgAnimation {
+            moves: first.move_number + 1..last.move_number + 1,
+            move_times,
+            end,
+        });
+        let document = lib::make_svg(last, &options)?;
+        return OutputWriter::new().write_to_file(outfile, &document);
+    }
+    let documents = frames
+        .iter()
+        .map(|(goban, delay)| Ok((lib::make_svg(goban, &options)?, *delay)))
+        .collect::<Result<Vec<_>, lib::GobanSVGError>>()?;
+    OutputWriter::new().save_animation(outfile, &documents)
+}
+
+#[cfg(not(feature = "pdf"))]
+fn render_book(_parsed_args: &args::SgfRenderArgs) -> Result<(), Box<dyn Error>> {
+    Err(SgfRenderError::NoPdfSupport)?
+}
+
+/// Returns a numbered filename, e.g. `fig-01.svg` for the first figure with `fig.svg`.
+fn numbered_filename(outfile: &Path, number: u64, digits: usize) -> PathBuf {
+    let stem = outfile
+        .file_stem()
+        .and_then(std::ffi::OsStr::to_str)
+        .unwrap_or("");
+    let mut filename = format!("{}-{:0width$}", stem, number, width = digits);
+    if let Some(extension) = outfile.extension().and_then(std::ffi::OsStr::to_str) {
+        filename = format!("{}.{}", filename, extension);
+    }
+    outfile.with_file_name(filename)
+}
+
+fn get_sgf_root(
+    infile: &Option<PathBuf>,
+    game: usize,
+) -> Result<sgf_parse::SgfNode, Box<dyn Error>> {
+    select_game(get_sgf_collection(infile)?, game)
+}
+
+fn select_game(
+    collection: Vec<sgf_parse::SgfNode>,
+    game: usize,
+) -> Result<sgf_parse::SgfNode, Box<dyn Error>> {
+    if collection.is_empty() {
+        Err(SgfRenderError::NoSgfNodes)?;
+    }
+    collection
+        .into_iter()
+        .nth(game - 1)
+        .ok_or_else(|| SgfRenderError::GameNotFound(game).into())
+}
+
+fn get_sgf_collection(infile: &Option<PathBuf>) -> Result<Vec<sgf_parse::SgfNode>, Box<dyn Error>> {
+    Ok(sgf_parse::parse(&read_input(infile)?)?)
+}
+
+/// Reads the whole input file, or stdin if there isn't one.
+fn read_input(infile: &Option<PathBuf>) -> Result<String, Box<dyn Error>> {
     let mut reader: Box<dyn std::io::Read> = match infile {
         Some(filename) => Box::new(std::io::BufReader::new(std::fs::File::open(&filename)?)),
         None => Box::new(std::io::stdin()),
     };
     let mut text = String::new();
     reader.read_to_string(&mut text)?;
-    let collection = sgf_parse::parse(&text)?;
-    collection
-        .into_iter()
-        .next()
-        .ok_or(Box::new(SgfRenderError::NoSgfNodes))
+    Ok(text)
 }
 
-fn write_to_file(outfile: &std::path::PathBuf, document: &SVG) -> Result<(), Box<dyn Error>> {
-    match outfile.extension().and_then(std::ffi::OsStr::to_str) {
-        Some("svg") => svg::save(&outfile, document)?,
-        Some("png") => save_png(&outfile, document)?,
-        _ => Err(SgfRenderError::UnsupportedFileExtension)?,
+/// Prints one line per game in the collection with the players, name, date and event.
+fn list_games(infile: &Option<PathBuf>) -> Result<(), Box<dyn Error>> {
+    let collection = get_sgf_collection(infile)?;
+    for (i, sgf_root) in collection.iter().enumerate() {
+        let mut line = format!(
+            "{}: {} vs {}",
+            i + 1,
+            game_info(sgf_root, "PB").unwrap_or_else(|| "?".to_string()),
+            game_info(sgf_root, "PW").unwrap_or_else(|| "?".to_string()),
+        );
+        for identifier in &["GN", "DT", "EV"] {
+            if let Some(text) = game_info(sgf_root, identifier) {
+                line = format!("{}, {}", line, text);
+            }
+        }
+        println!("{}", line);
     }
-    Ok(())
-}
 
-#[cfg(feature = "png")]
-fn save_png(outfile: &PathBuf, document: &SVG) -> Result<(), Box<dyn Error>> {
-    let s = document.to_string();
-    let mut fontdb = usvg::fontdb::Database::new();
-    let font_data = include_bytes!("../data/Roboto-Bold.ttf").to_vec();
-    fontdb.load_font_data(font_data);
-    let tree = usvg::Tree::from_str(
-        &s,
-        &usvg::Options {
-            fontdb,
-            ..Default::default()
-        },
-    )?;
-    let img =
-        resvg::render(&tree, usvg::FitTo::Original, None).ok_or(SgfRenderError::PNGRenderFailed)?;
-    img.save_png(outfile)?;
     Ok(())
 }
 
-#[cfg(not(feature = "png"))]
-fn save_png(_outfile: &PathBuf, _document: &SVG) -> Result<(), Box<dyn Error>> {
-    Err(SgfRenderError::NoPngSupport)?
+/// Returns the value of a simple text game info property like PB or GN.
+fn game_info(sgf_root: &sgf_parse::SgfNode, identifier: &str) -> Option<String> {
+    match sgf_root.get_property(identifier)? {
+        SgfProp::AN(text)
+        | SgfProp::BR(text)
+        | SgfProp::BT(text)
+        | SgfProp::CP(text)
+        | SgfProp::DT(text)
+        | SgfProp::EV(text)
+        | SgfProp::GN(text)
+        | SgfProp::ON(text)
+        | SgfProp::OT(text)
+        | SgfProp::PB(text)
+        | SgfProp::PC(text)
+        | SgfProp::PW(text)
+        | SgfProp::RE(text)
+        | SgfProp::RO(text)
+        | SgfProp::RU(text)
+        | SgfProp::SO(text)
+        | SgfProp::US(text)
+        | SgfProp::WR(text)
+        | SgfProp::WT(text) => Some(text.to_string()),
+        _ => None,
+    }
 }
 
 #[derive(Debug)]
@@ -119,6 +464,14 @@ enum SgfRenderError {
     PNGRenderFailed,
     UnsupportedFileExtension,
     NoPngSupport,
+    NoPdfSupport,
+    NoOutfile,
+    MissingVariation(String),
+    GameNotFound(usize),
+    NoInputFiles,
+    BatchFailures(usize),
+    DuplicateOutputFile(String),
+    InvalidOutputFile(String),
 }
 
 impl std::fmt::Display for SgfRenderError {
@@ -129,6 +482,22 @@ impl std::fmt::Display for SgfRenderError {
             Self::PNGRenderFailed => write!(f, "Rendering png failed."),
             Self::UnsupportedFileExtension => write!(f, "Unsupported file extension."),
             Self::NoPngSupport => write!(f, "Compiled without png support."),
+            Self::NoPdfSupport => write!(f, "Compiled without pdf support."),
+            Self::NoOutfile => write!(f, "An output file is required."),
+            Self::MissingVariation(path) => write!(f, "Variation not found: {}", path),
+            Self::GameNotFound(game) => write!(f, "Game {} not found in collection.", game),
+            Self::NoInputFiles => write!(f, "No input files found."),
+            Self::BatchFailures(count) => write!(f, "{} files or games failed.", count),
+            Self::DuplicateOutputFile(filename) => {
+                write!(
+                    f,
+                    "Output file {} already written for another game.",
+                    filename
+                )
+            }
+            Self::InvalidOutputFile(filename) => {
+                write!(f, "Output file name '{}' isn't usable.", filename)
+            }
         }
     }
 }
@@ -0,0 +1,277 @@
+#[cfg(feature = "png")]
+use crate::animation;
+use crate::lib::{self, Goban, MakeSvgOptions};
+#[cfg(feature = "pdf")]
+use crate::pdf;
+use crate::SgfRenderError;
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+use svg::node::element::SVG;
+
+/// Formats that can be picked with `--format` rather than by file extension.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum OutputFormat {
+    Svg,
+    Ascii,
+    /// Box-drawing characters, optionally with ANSI colors.
+    Unicode {
+        color: bool,
+    },
+    /// Sensei's Library wiki diagram.
+    Sensei,
+    /// LaTeX igo package macros.
+    Igo,
+    /// LaTeX psgo package environment.
+    Psgo,
+}
+
+/// Writes diagrams to files.
+///
+/// Keeps anything that's expensive to set up (like the font database for png and pdf rendering)
+/// around so that writing many files doesn't repeat the work.
+pub struct OutputWriter {
+    #[cfg(any(feature = "png", feature = "pdf"))]
+    usvg_options: usvg::Options<'static>,
+}
+
+impl OutputWriter {
+    #[cfg(any(feature = "png", feature = "pdf"))]
+    pub fn new() -> Self {
+        let mut usvg_options = usvg::Options::default();
+        let fontdb = usvg_options.fontdb_mut();
+        let font_data = include_bytes!("../data/Roboto-Bold.ttf").to_vec();
+        fontdb.load_font_data(font_data);
+        // Just the numerals from M+ 1p, for kanji coordinates. Text falls back to it for
+        // characters Roboto doesn't have.
+        let font_data = include_bytes!("../data/MPLUS1p-Numerals.ttf").to_vec();
+        fontdb.load_font_data(font_data);
+        Self { usvg_options }
+    }
+
+    #[cfg(not(any(feature = "png", feature = "pdf")))]
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Writes a diagram of the goban to `outfile`, or to stdout if there isn't one.
+    ///
+    /// The format is `format` if it's set, and otherwise comes from the file extension, with SVG
+    /// for stdout.
+    pub fn write_diagram(
+        &self,
+        outfile: Option<&Path>,
+        goban: &Goban,
+        options: &MakeSvgOptions,
+        format: Option<OutputFormat>,
+    ) -> Result<(), Box<dyn Error>> {
+        let extension = outfile
+            .and_then(Path::extension)
+            .and_then(std::ffi::OsStr::to_str);
+        let format = format.or(match extension {
+            Some("txt") => Some(OutputFormat::Ascii),
+            Some("tex") => Some(OutputFormat::Igo),
+            _ => None,
+        });
+        // Explicit formats are all written as text, which would make a broken image file.
+        if format.is_some() && matches!(extension, Some("png" | "pdf" | "gif" | "apng")) {
+            Err(SgfRenderError::UnsupportedFileExtension)?;
+        }
+        match format {
+            Some(OutputFormat::Ascii) => write_text(outfile, &lib::make_ascii(goban, options)?),
+            Some(OutputFormat::Unicode { color }) => {
+                write_text(outfile, &lib::make_unicode(goban, options, color)?)
+            }
+            Some(OutputFormat::Sensei) => write_text(outfile, &lib::make_sensei(goban, options)?),
+            Some(OutputFormat::Igo) => write_text(outfile, &lib::make_igo(goban, options)?),
+            Some(OutputFormat::Psgo) => write_text(outfile, &lib::make_psgo(goban, options)?),
+            Some(OutputFormat::Svg) | None => {
+                let document = lib::make_svg(goban, options)?;
+                match (outfile, format) {
+                    (Some(outfile), None) => self.write_to_file(outfile, &document),
+                    (Some(outfile), Some(_)) => Ok(svg::save(outfile, &document)?),
+                    (None, _) => Ok(svg::write(std::io::stdout(), &document)?),
+                }
+            }
+        }
+    }
+
+    pub fn write_to_file(&self, outfile: &Path, document: &SVG) -> Result<(), Box<dyn Error>> {
+        match outfile.extension().and_then(std::ffi::OsStr::to_str) {
+            Some("svg") => svg::save(outfile, document)?,
+            Some("png") => self.save_png(outfile, document)?,
+            Some("pdf") => self.save_pdf(outfile, document)?,
+            Some("gif") | Some("apng") => self.save_animation(outfile, &[(document.clone(), 0)])?,
+            _ => Err(SgfRenderError::UnsupportedFileExtension)?,
+        }
+        Ok(())
+    }
+
+    /// Writes an animation with each document shown for its delay in milliseconds.
+    ///
+    /// The format is picked from the file extension, which must be `gif` or `apng`.
+    #[cfg(feature = "png")]
+    pub fn save_animation(
+        &self,
+        outfile: &Path,
+        frames: &[(SVG, u32)],
+    ) -> Result<(), Box<dyn Error>> {
+        let extension = outfile.extension().and_then(std::ffi::OsStr::to_str);
+        if extension != Some("gif") && extension != Some("apng") {
+            Err(SgfRenderError::UnsupportedFileExtension)?;
+        }
+        let mut rendered = vec![];
+        for (document, delay) in frames {
+            let pixmap = self.render(document)?;
+            rendered.push((
+                (pixmap.width(), pixmap.height()),
+                demultiplied(&pixmap),
+                *delay,
+            ));
+        }
+        // Captions can make some frames taller than others, so pad them all to the largest.
+        let width = rendered
+            .iter()
+            .map(|(size, _, _)| size.0)
+            .max()
+            .unwrap_or(0);
+        let height = rendered
+            .iter()
+            .map(|(size, _, _)| size.1)
+            .max()
+            .unwrap_or(0);
+        let images: Vec<_> = rendered
+            .into_iter()
+            .map(|(size, data, delay)| animation::Frame {
+                data: animation::pad_image(&data, size, (width, height)),
+                delay,
+            })
+            .collect();
+        let file = std::io::BufWriter::new(std::fs::File::create(outfile)?);
+        if extension == Some("gif") {
+            animation::write_gif(file, width, height, &images)
+        } else {
+            animation::write_apng(file, width, height, &images)
+        }
+    }
+
+    #[cfg(not(feature = "png"))]
+    pub fn save_animation(
+        &self,
+        _outfile: &Path,
+        _frames: &[(SVG, u32)],
+    ) -> Result<(), Box<dyn Error>> {
+        Err(SgfRenderError::NoPngSupport)?
+    }
+
+    #[cfg(feature = "png")]
+    fn save_png(&self, outfile: &Path, document: &SVG) -> Result<(), Box<dyn Error>> {
+        self.render(document)?.save_png(outfile)?;
+        Ok(())
+    }
+
+    /// Rasterizes a document at its own size.
+    #[cfg(feature = "png")]
+    fn render(&self, document: &SVG) -> Result<resvg::tiny_skia::Pixmap, Box<dyn Error>> {
+        let tree = usvg::Tree::from_str(&document.to_string(), &self.usvg_options)?;
+        let size = tree.size().to_int_size();
+        let mut pixmap = resvg::tiny_skia::Pixmap::new(size.width(), size.height())
+            .ok_or(SgfRenderError::PNGRenderFailed)?;
+        resvg::render(
+            &tree,
+            resvg::tiny_skia::Transform::default(),
+            &mut pixmap.as_mut(),
+        );
+        Ok(pixmap)
+    }
+
+    #[cfg(not(feature = "png"))]
+    fn save_png(&self, _outfile: &Path, _document: &SVG) -> Result<(), Box<dyn Error>> {
+        Err(SgfRenderError::NoPngSupport)?
+    }
+
+    #[cfg(feature = "pdf")]
+    fn save_pdf(&self, outfile: &Path, document: &SVG) -> Result<(), Box<dyn Error>> {
+        let file = std::io::BufWriter::new(std::fs::File::create(outfile)?);
+        pdf::write_document_pdf(file, document, &self.usvg_options)
+    }
+
+    /// Writes the pages to a multi-page PDF.
+    #[cfg(feature = "pdf")]
+    pub fn save_pdf_pages(
+        &self,
+        outfile: &Path,
+        pages: &[pdf::Page],
+    ) -> Result<(), Box<dyn Error>> {
+        if outfile.extension().and_then(std::ffi::OsStr::to_str) != Some("pdf") {
+            Err(SgfRenderError::UnsupportedFileExtension)?;
+        }
+        let file = std::io::BufWriter::new(std::fs::File::create(outfile)?);
+        pdf::write_pdf(file, pages, &self.usvg_options)
+    }
+
+    #[cfg(not(feature = "pdf"))]
+    fn save_pdf(&self, _outfile: &Path, _document: &SVG) -> Result<(), Box<dyn Error>> {
+        Err(SgfRenderError::NoPdfSupport)?
+    }
+}
+
+/// Returns a pixmap's pixels as straight RGBA, since resvg draws with premultiplied alpha.
+#[cfg(feature = "png")]
+fn demultiplied(pixmap: &resvg::tiny_skia::Pixmap) -> Vec<u8> {
+    pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            vec![color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect()
+}
+
+fn write_text(outfile: Option<&Path>, text: &str) -> Result<(), Box<dyn Error>> {
+    match outfile {
+        Some(outfile) => std::fs::write(outfile, text)?,
+        None => std::io::stdout().lock().write_all(text.as_bytes())?,
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::{Coordinates, CroppedEdges, GobanRange, Theme};
+
+    #[test]
+    fn explicit_formats_refuse_image_extensions() {
+        let options = MakeSvgOptions {
+            goban_range: GobanRange::FullBoard,
+            viewbox_width: 800.0,
+            render_labels: false,
+            coordinates: Coordinates::default(),
+            render_score: false,
+            move_numbers: None,
+            animation: None,
+            cropped_edges: CroppedEdges::Lines,
+            theme: Theme::default(),
+        };
+        let outfile = std::env::temp_dir().join("sgf-render-format-mismatch.png");
+        let result = OutputWriter::new().write_diagram(
+            Some(&outfile),
+            &Goban::new((9, 9)),
+            &options,
+            Some(OutputFormat::Svg),
+        );
+        assert!(result.is_err());
+        assert!(!outfile.exists());
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn animation_frames_are_demultiplied() {
+        let mut pixmap = resvg::tiny_skia::Pixmap::new(1, 1).unwrap();
+        pixmap.fill(resvg::tiny_skia::Color::from_rgba8(200, 100, 50, 51));
+        assert_eq!(pixmap.data(), &[40, 20, 10, 51]);
+        assert_eq!(demultiplied(&pixmap), vec![200, 100, 50, 51]);
+    }
+}
@@ -0,0 +1,263 @@
+//! PDF output, converting diagrams with `svg2pdf`.
+//!
+//! Text is embedded in the fonts loaded by the `OutputWriter`, so the output doesn't depend on any
+//! fonts being installed where it's printed.
+
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref};
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Write;
+use svg::node::element::SVG;
+use svg::Document;
+
+/// SVG pixels per inch, to match PDF's 72 points to the inch.
+const PIXELS_PER_INCH: f32 = 96.0;
+
+/// A page of a PDF, with sizes in SVG pixels.
+pub struct Page {
+    pub width: f64,
+    pub height: f64,
+    pub items: Vec<PageItem>,
+}
+
+/// A document placed on a page.
+///
+/// The document is scaled to fit inside the box, keeping its aspect ratio, and centered
+/// horizontally at the top of the box.
+pub struct PageItem {
+    pub document: SVG,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug)]
+pub enum PdfError {
+    ConversionFailed(svg2pdf::ConversionError),
+}
+
+impl std::fmt::Display for PdfError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ConversionFailed(e) => write!(f, "Converting to pdf failed: {}", e),
+        }
+    }
+}
+
+impl Error for PdfError {}
+
+/// Writes a single document to a PDF, on a page just big enough for it.
+pub fn write_document_pdf(
+    mut w: impl Write,
+    document: &SVG,
+    options: &usvg::Options,
+) -> Result<(), Box<dyn Error>> {
+    let tree = usvg::Tree::from_str(&document.to_string(), options)?;
+    let page_options = svg2pdf::PageOptions {
+        dpi: PIXELS_PER_INCH,
+    };
+    let pdf = svg2pdf::to_pdf(&tree, svg2pdf::ConversionOptions::default(), page_options)
+        .map_err(PdfError::ConversionFailed)?;
+    w.write_all(&pdf)?;
+
+    Ok(())
+}
+
+/// Writes the pages to a PDF.
+///
+/// Each page is drawn as a single SVG document nesting its items, which `svg2pdf` converts to a
+/// form XObject filling the page.
+pub fn write_pdf(
+    mut w: impl Write,
+    pages: &[Page],
+    options: &usvg::Options,
+) -> Result<(), Box<dyn Error>> {
+    let mut alloc = Ref::new(1);
+    let catalog_id = alloc.bump();
+    let page_tree_id = alloc.bump();
+    let mut pdf = Pdf::new();
+    let mut page_ids = vec![];
+    for page in pages {
+        let tree = usvg::Tree::from_str(&page_document(page).to_string(), options)?;
+        let (chunk, svg_id) = svg2pdf::to_chunk(&tree, svg2pdf::ConversionOptions::default())
+            .map_err(PdfError::ConversionFailed)?;
+        let mut ids = HashMap::new();
+        let chunk = chunk.renumber(|old| *ids.entry(old).or_insert_with(|| alloc.bump()));
+        let page_id = alloc.bump();
+        let content_id = alloc.bump();
+        let scale = 72.0 / PIXELS_PER_INCH;
+        let (width, height) = (page.width as f32 * scale, page.height as f32 * scale);
+
+        let mut pdf_page = pdf.page(page_id);
+        pdf_page.media_box(Rect::new(0.0, 0.0, width, height));
+        pdf_page.parent(page_tree_id);
+        pdf_page.contents(content_id);
+        pdf_page
+            .resources()
+            .x_objects()
+            .pair(Name(b"S0"), ids[&svg_id]);
+        pdf_page.finish();
+
+        // The XObject is a point square, so it's scaled up to the page.
+        let mut content = Content::new();
+        content
+            .transform([width, 0.0, 0.0, height, 0.0, 0.0])
+            .x_object(Name(b"S0"));
+        pdf.stream(content_id, &content.finish());
+        pdf.extend(&chunk);
+        page_ids.push(page_id);
+    }
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id)
+        .kids(page_ids.iter().copied())
+        .count(page_ids.len() as i32);
+    w.write_all(&pdf.finish())?;
+
+    Ok(())
+}
+
+/// Nests a page's items in a document the size of the page.
+fn page_document(page: &Page) -> Document {
+    let mut document = Document::new()
+        .set("viewBox", (0.0, 0.0, page.width, page.height))
+        .set("width", page.width)
+        .set("height", page.height);
+    for item in &page.items {
+        document = document.add(
+            item.document
+                .clone()
+                .set("x", item.x)
+                .set("y", item.y)
+                .set("width", item.width)
+                .set("height", item.height)
+                .set("preserveAspectRatio", "xMidYMin meet"),
+        );
+    }
+
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use svg::node::element;
+
+    fn options() -> usvg::Options<'static> {
+        let mut options = usvg::Options::default();
+        options
+            .fontdb_mut()
+            .load_font_data(include_bytes!("../data/Roboto-Bold.ttf").to_vec());
+        options
+    }
+
+    fn diagram(text: &str) -> SVG {
+        Document::new()
+            .set("viewBox", (0, 0, 100, 50))
+            .set("width", 100)
+            .add(
+                element::Rectangle::new()
+                    .set("width", 100)
+                    .set("height", 50)
+                    .set("fill", "#cfa87e"),
+            )
+            .add(
+                element::Text::new()
+                    .set("x", 10)
+                    .set("y", 30)
+                    .set("font-family", "Roboto")
+                    .set("font-size", 20)
+                    .add(svg::node::Text::new(text)),
+            )
+    }
+
+    /// Loads a PDF back, checking that its text is written with an embedded TrueType font.
+    fn load(pdf: &[u8]) -> lopdf::Document {
+        let document = lopdf::Document::load_mem(pdf).unwrap();
+        let dictionaries: Vec<&lopdf::Dictionary> = document
+            .objects
+            .values()
+            .filter_map(|object| match object {
+                lopdf::Object::Dictionary(dictionary) => Some(dictionary),
+                lopdf::Object::Stream(stream) => Some(&stream.dict),
+                _ => None,
+            })
+            .collect();
+        let font_file = dictionaries
+            .iter()
+            .find_map(|dictionary| dictionary.get(b"FontFile2").ok())
+            .and_then(|font_file| font_file.as_reference().ok())
+            .unwrap();
+        let mut font_file = document
+            .get_object(font_file)
+            .unwrap()
+            .as_stream()
+            .unwrap()
+            .clone();
+        font_file.decompress().unwrap();
+        assert!(font_file.content.starts_with(&[0, 1, 0, 0]));
+        let text_shown = document.objects.values().any(|object| match object {
+            lopdf::Object::Stream(stream) => {
+                let mut stream = stream.clone();
+                let _ = stream.decompress();
+                stream
+                    .content
+                    .windows(3)
+                    .any(|window| window == b"Tj\n" || window == b"TJ\n")
+            }
+            _ => false,
+        });
+        assert!(text_shown);
+
+        document
+    }
+
+    fn media_box(document: &lopdf::Document, page: lopdf::ObjectId) -> Vec<f32> {
+        document
+            .get_dictionary(page)
+            .unwrap()
+            .get(b"MediaBox")
+            .and_then(|media_box| media_box.as_array())
+            .unwrap()
+            .iter()
+            .map(|value| value.as_float().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn documents_load_back_on_a_page_their_size() {
+        let mut pdf = vec![];
+        write_document_pdf(&mut pdf, &diagram("Black to play"), &options()).unwrap();
+
+        let document = load(&pdf);
+        let pages: Vec<_> = document.get_pages().into_values().collect();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(media_box(&document, pages[0]), vec![0.0, 0.0, 75.0, 37.5]);
+    }
+
+    #[test]
+    fn pages_load_back_in_order() {
+        let page = |items: usize| Page {
+            width: 400.0,
+            height: 600.0,
+            items: (0..items)
+                .map(|i| PageItem {
+                    document: diagram("Problem"),
+                    x: 0.0,
+                    y: 100.0 * i as f64,
+                    width: 200.0,
+                    height: 100.0,
+                })
+                .collect(),
+        };
+        let mut pdf = vec![];
+        write_pdf(&mut pdf, &[page(2), page(1)], &options()).unwrap();
+
+        let document = load(&pdf);
+        let pages: Vec<_> = document.get_pages().into_values().collect();
+        assert_eq!(pages.len(), 2);
+        for &page in &pages {
+            assert_eq!(media_box(&document, page), vec![0.0, 0.0, 300.0, 450.0]);
+        }
+    }
+}